@@ -1,27 +1,49 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program::MAX_RETURN_DATA;
+use base64::{engine::general_purpose, Engine as _};
 use anchor_spl::token::{self, Mint, Token, TokenAccount};
-use anchor_spl::associated_token::AssociatedToken;
-use mpl_token_metadata::types::{Creator, DataV2};
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use anchor_spl::metadata::Metadata as TokenMetadata;
+use mpl_token_metadata::accounts::Metadata;
+use mpl_token_metadata::types::{Collection, CollectionDetails, Creator, DataV2};
 
 declare_id!("9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM");
 
-const MAX_SUPPLY: u64 = 50;
-const MAX_LICENSES: usize = 50; // For Vec sizing
+const MAX_LICENSES: usize = 50; // For legacy has_license Vec sizing
 
-// Default URIs for new mints
-const DEFAULT_IMAGE_URI: &str = "https://tan-everyday-mite-419.mypinata.cloud/ipfs/bafkreialme2ca3b36nzq5rqqdqaw3k2le4uvgrdxtdj33t2j4sn44amisi";
+// Default provenance for new mints. There is no default image - licenses without one
+// use the SVG rendered on-chain by render_license_svg, served through get_token_uri,
+// and their Metaplex uri is left empty.
 const DEFAULT_PROVENANCE_CID: &str = "https://tan-everyday-mite-419.mypinata.cloud/ipfs/bafkreidc7qbkdsfirbetsu5owm56oeqkhwhqlxpfgjio4qy3xexigod2nq";
+const MAX_URI_LENGTH: usize = 200; // For image and provenance URI String sizing
+const MAX_NAME_LENGTH: usize = 100; // For LicenseMetadata name sizing
+const DEFAULT_NAME_PREFIX: &str = "Brolli";
+const DEFAULT_SYMBOL: &str = "BROLLI";
+// For LicenseTerms String sizing
+const MAX_LICENSOR_NAME_LENGTH: usize = 64;
+const MAX_PATENT_NUMBER_LENGTH: usize = 32;
+const MAX_PATENT_TITLE_LENGTH: usize = 128;
+const MAX_GOVERNING_LAW_LENGTH: usize = 64;
+const MAX_PROVENANCE_HASH_LENGTH: usize = 100;
+const AIRDROP_ACCOUNTS_PER_ENTRY: usize = 8; // remaining_accounts per batch airdrop recipient
 
 #[program]
 pub mod brolli_license {
     use super::*;
 
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+    pub fn initialize(ctx: Context<Initialize>, max_supply: u64, mint_price: u64) -> Result<()> {
         let license_state = &mut ctx.accounts.license_state;
         license_state.authority = ctx.accounts.authority.key();
         license_state.current_supply = 0;
-        license_state.max_supply = MAX_SUPPLY;
-        license_state.has_license = Vec::new();
+        license_state.max_supply = max_supply;
+        license_state.default_image_uri = String::new();
+        license_state.default_provenance_cid = DEFAULT_PROVENANCE_CID.to_string();
+        license_state.mint_price = mint_price;
+        license_state.phase = MintPhase::Public;
+        license_state.name_prefix = DEFAULT_NAME_PREFIX.to_string();
+        license_state.symbol = DEFAULT_SYMBOL.to_string();
         Ok(())
     }
 
@@ -30,123 +52,526 @@ pub mod brolli_license {
         name: String,
         image_uri: String,
         provenance_cid: String,
+        terms: LicenseTerms,
     ) -> Result<()> {
-        // Check supply limit and user eligibility first
+        require!(
+            ctx.accounts.license_state.phase == MintPhase::Public,
+            LicenseError::WrongPhase
+        );
+        process_mint_license(ctx, name, image_uri, provenance_cid, terms, None)
+    }
+
+    // Early-adopter mint at a discount - the signer must be a leaf of the allowlist Merkle root
+    pub fn mint_license_allowlisted(
+        ctx: Context<MintLicense>,
+        name: String,
+        image_uri: String,
+        provenance_cid: String,
+        terms: LicenseTerms,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let license_state = &ctx.accounts.license_state;
+        require!(license_state.phase == MintPhase::Allowlist, LicenseError::WrongPhase);
+
+        let leaf = keccak::hashv(&[ctx.accounts.user.key().as_ref()]).0;
+        require!(
+            verify_merkle_proof(&proof, license_state.merkle_root, leaf),
+            LicenseError::InvalidMerkleProof
+        );
+
+        let discount_bps = license_state.allowlist_discount_bps;
+        process_mint_license(ctx, name, image_uri, provenance_cid, terms, Some(discount_bps))
+    }
+
+    // Minter-only: grant a license to a recipient who does not sign. The minter pays
+    // for the accounts; no mint price is charged and mint phases do not apply.
+    // The license is the recipient's from the start - LicenseMetadata.owner and the
+    // one-per-address receipt name them - but the token waits in a license_state
+    // escrow account until they run claim_license. Licenses are frozen through their
+    // master edition with the holder's approval, which a non-signing recipient can't
+    // give. Until claimed, the recipient can't transfer or burn the license; the
+    // authority can withdraw it with cancel_airdrop.
+    pub fn airdrop_license(
+        ctx: Context<AirdropLicense>,
+        name: String,
+        image_uri: String,
+        provenance_cid: String,
+        terms: LicenseTerms,
+    ) -> Result<()> {
+        validate_license_inputs(&name, &image_uri, &provenance_cid)?;
+        terms.validate()?;
+
+        // One license per address is enforced by the recipient's holder_receipt PDA
+        let new_token_id = ctx.accounts.license_state.next_token_id()?;
+        let metadata_name = ctx.accounts.license_state.metaplex_name(new_token_id, &name)?;
+        let recipient = ctx.accounts.recipient.key();
+
+        let holder_receipt = &mut ctx.accounts.holder_receipt;
+        holder_receipt.owner = recipient;
+        holder_receipt.mint = ctx.accounts.mint.key();
+        holder_receipt.token_id = new_token_id;
+        holder_receipt.price_paid = 0;
+        holder_receipt.auction_sale = false;
+
+        let (image_uri, provenance_cid) = ctx
+            .accounts
+            .license_state
+            .resolve_uris(image_uri, provenance_cid);
+        let collection = license_collection(
+            &ctx.accounts.license_state,
+            ctx.accounts.collection_mint.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.collection_metadata.as_ref().map(|account| account.to_account_info()),
+            ctx.accounts.collection_master_edition.as_ref().map(|account| account.to_account_info()),
+        )?;
+
+        mint_license_nft(
+            LicenseNftAccounts {
+                license_state: ctx.accounts.license_state.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                metadata: ctx.accounts.metadata.to_account_info(),
+                master_edition: ctx.accounts.master_edition.to_account_info(),
+                token_account: ctx.accounts.token_account.to_account_info(),
+                holder: None,
+                payer: ctx.accounts.minter.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                collection,
+            },
+            ctx.bumps.license_state,
+            metadata_name,
+            ctx.accounts.license_state.symbol.clone(),
+            image_uri.clone(),
+        )?;
+
+        let license_metadata = &mut ctx.accounts.license_metadata;
+        license_metadata.name = name.clone();
+        license_metadata.image_uri = image_uri;
+        license_metadata.provenance_cid = provenance_cid.clone();
+        license_metadata.owner = recipient;
+        license_metadata.mint = ctx.accounts.mint.key();
+        license_metadata.token_id = new_token_id;
+        (license_metadata.valid_from, license_metadata.valid_until) = ctx
+            .accounts
+            .license_state
+            .validity_window(Clock::get()?.unix_timestamp)?;
+
+        let license_terms = &mut ctx.accounts.license_terms;
+        license_terms.mint = ctx.accounts.mint.key();
+        license_terms.terms = terms;
+
+        emit!(LicenseMinted {
+            user: recipient,
+            mint: ctx.accounts.mint.key(),
+            name,
+            provenance_cid,
+            token_id: new_token_id,
+        });
+
+        Ok(())
+    }
+
+    // Minter-only: airdrop_license for a whole cohort in one instruction. For each entry,
+    // remaining_accounts holds [mint, metadata, master_edition, license_metadata,
+    // license_terms, token_account, holder_receipt, recipient] in that order; every
+    // PDA is checked before it is created. token_account is license_state's escrow
+    // account for the mint, as in airdrop_license.
+    pub fn batch_airdrop_licenses<'info>(
+        ctx: Context<'_, '_, '_, 'info, BatchAirdropLicenses<'info>>,
+        entries: Vec<AirdropEntry>,
+    ) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len() == entries.len() * AIRDROP_ACCOUNTS_PER_ENTRY,
+            ErrorCode::AccountNotEnoughKeys
+        );
+        // All or nothing - the whole batch must fit in the remaining supply
         {
             let license_state = &ctx.accounts.license_state;
             require!(
-                license_state.current_supply < license_state.max_supply,
+                license_state.current_supply + entries.len() as u64 <= license_state.max_supply,
                 LicenseError::MaximumSupplyReached
             );
+        }
+
+        // Reject bad input before any account is created
+        for entry in &entries {
+            validate_license_inputs(&entry.name, &entry.image_uri, &entry.provenance_cid)?;
+            entry.terms.validate()?;
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let license_state_info = ctx.accounts.license_state.to_account_info();
+        let minter_info = ctx.accounts.minter.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+        let token_program_info = ctx.accounts.token_program.to_account_info();
+
+        for (entry, accounts) in entries
+            .into_iter()
+            .zip(ctx.remaining_accounts.chunks(AIRDROP_ACCOUNTS_PER_ENTRY))
+        {
+            let [mint, metadata, master_edition, license_metadata, license_terms, token_account, holder_receipt, recipient] =
+                accounts
+            else {
+                return err!(ErrorCode::AccountNotEnoughKeys);
+            };
+            require_keys_eq!(recipient.key(), entry.recipient, ErrorCode::ConstraintAddress);
+
+            let new_token_id = ctx.accounts.license_state.next_token_id()?;
+            let metadata_name = ctx
+                .accounts
+                .license_state
+                .metaplex_name(new_token_id, &entry.name)?;
+            let token_id_bytes = new_token_id.to_le_bytes();
+
+            // Mint PDA, initialized like MintLicense's `mint` account
+            let (mint_key, mint_bump) =
+                Pubkey::find_program_address(&[b"mint", token_id_bytes.as_ref()], &crate::ID);
+            require_keys_eq!(mint.key(), mint_key, ErrorCode::ConstraintSeeds);
+            create_pda_account(
+                &minter_info,
+                mint,
+                &system_program_info,
+                Mint::LEN,
+                &token::ID,
+                &[b"mint", token_id_bytes.as_ref(), &[mint_bump]],
+            )?;
+            token::initialize_mint2(
+                CpiContext::new(
+                    token_program_info.clone(),
+                    token::InitializeMint2 { mint: mint.clone() },
+                ),
+                0,
+                &license_state_info.key(),
+                Some(&license_state_info.key()),
+            )?;
+
+            let (metadata_key, _) = Pubkey::find_program_address(
+                &[b"metadata", mpl_token_metadata::ID.as_ref(), mint_key.as_ref()],
+                &mpl_token_metadata::ID,
+            );
+            require_keys_eq!(metadata.key(), metadata_key, ErrorCode::ConstraintSeeds);
+            let (master_edition_key, _) = Pubkey::find_program_address(
+                &[b"metadata", mpl_token_metadata::ID.as_ref(), mint_key.as_ref(), b"edition"],
+                &mpl_token_metadata::ID,
+            );
+            require_keys_eq!(master_edition.key(), master_edition_key, ErrorCode::ConstraintSeeds);
+
+            require_keys_eq!(
+                token_account.key(),
+                get_associated_token_address(&license_state_info.key(), &mint_key),
+                ErrorCode::ConstraintAssociated
+            );
+            anchor_spl::associated_token::create(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                anchor_spl::associated_token::Create {
+                    payer: minter_info.clone(),
+                    associated_token: token_account.clone(),
+                    authority: license_state_info.clone(),
+                    mint: mint.clone(),
+                    system_program: system_program_info.clone(),
+                    token_program: token_program_info.clone(),
+                },
+            ))?;
 
+            // One license per address - creating the receipt fails if the recipient holds one
+            let (receipt_key, receipt_bump) = Pubkey::find_program_address(
+                &[b"holder", entry.recipient.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(holder_receipt.key(), receipt_key, ErrorCode::ConstraintSeeds);
             require!(
-                !license_state.has_license.contains(&ctx.accounts.user.key()),
+                holder_receipt.owner != &crate::ID,
                 LicenseError::AlreadyHasLicense
             );
+            create_pda_account(
+                &minter_info,
+                holder_receipt,
+                &system_program_info,
+                8 + HolderReceipt::SPACE,
+                &crate::ID,
+                &[b"holder", entry.recipient.as_ref(), &[receipt_bump]],
+            )?;
+            HolderReceipt {
+                owner: entry.recipient,
+                mint: mint_key,
+                token_id: new_token_id,
+                price_paid: 0,
+                auction_sale: false,
+            }
+            .try_serialize(&mut &mut holder_receipt.try_borrow_mut_data()?[..])?;
+
+            let (image_uri, provenance_cid) = ctx
+                .accounts
+                .license_state
+                .resolve_uris(entry.image_uri, entry.provenance_cid);
+            let (valid_from, valid_until) = ctx.accounts.license_state.validity_window(now)?;
+
+            mint_license_nft(
+                LicenseNftAccounts {
+                    license_state: license_state_info.clone(),
+                    mint: mint.clone(),
+                    metadata: metadata.clone(),
+                    master_edition: master_edition.clone(),
+                    token_account: token_account.clone(),
+                    holder: None,
+                    payer: minter_info.clone(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                    system_program: system_program_info.clone(),
+                    token_program: token_program_info.clone(),
+                    token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                    collection: license_collection(
+                        &ctx.accounts.license_state,
+                        ctx.accounts.collection_mint.as_ref().map(|account| account.to_account_info()),
+                        ctx.accounts.collection_metadata.as_ref().map(|account| account.to_account_info()),
+                        ctx.accounts.collection_master_edition.as_ref().map(|account| account.to_account_info()),
+                    )?,
+                },
+                ctx.bumps.license_state,
+                metadata_name,
+                ctx.accounts.license_state.symbol.clone(),
+                image_uri.clone(),
+            )?;
+
+            let (license_metadata_key, license_metadata_bump) = Pubkey::find_program_address(
+                &[b"metadata", mint_key.as_ref()],
+                &crate::ID,
+            );
+            require_keys_eq!(license_metadata.key(), license_metadata_key, ErrorCode::ConstraintSeeds);
+            create_pda_account(
+                &minter_info,
+                license_metadata,
+                &system_program_info,
+                8 + LicenseMetadata::SPACE,
+                &crate::ID,
+                &[b"metadata", mint_key.as_ref(), &[license_metadata_bump]],
+            )?;
+            LicenseMetadata {
+                name: entry.name.clone(),
+                image_uri,
+                provenance_cid: provenance_cid.clone(),
+                owner: entry.recipient,
+                mint: mint_key,
+                token_id: new_token_id,
+                revoked_at: None,
+                revocation_reason: 0,
+                valid_from,
+                valid_until,
+            }
+            .try_serialize(&mut &mut license_metadata.try_borrow_mut_data()?[..])?;
+
+            let (license_terms_key, license_terms_bump) =
+                Pubkey::find_program_address(&[b"terms", mint_key.as_ref()], &crate::ID);
+            require_keys_eq!(license_terms.key(), license_terms_key, ErrorCode::ConstraintSeeds);
+            create_pda_account(
+                &minter_info,
+                license_terms,
+                &system_program_info,
+                8 + LicenseTermsAccount::SPACE,
+                &crate::ID,
+                &[b"terms", mint_key.as_ref(), &[license_terms_bump]],
+            )?;
+            LicenseTermsAccount {
+                mint: mint_key,
+                terms: entry.terms,
+            }
+            .try_serialize(&mut &mut license_terms.try_borrow_mut_data()?[..])?;
+
+            emit!(LicenseMinted {
+                user: entry.recipient,
+                mint: mint_key,
+                name: entry.name,
+                provenance_cid,
+                token_id: new_token_id,
+            });
         }
 
-        // Now update state (get mutable reference)
-        let new_token_id = {
-            let license_state = &mut ctx.accounts.license_state;
-            license_state.has_license.push(ctx.accounts.user.key());
-            license_state.current_supply += 1;
-            license_state.current_supply
-        };
+        Ok(())
+    }
 
-        // Mint the NFT
+    // Recipient-only: take delivery of an airdropped license. The token moves out of
+    // escrow into the recipient's own account, which is locked like any other license,
+    // and the escrow's rent goes to the recipient.
+    pub fn claim_license(ctx: Context<ClaimLicense>) -> Result<()> {
         let license_state_seeds = &[
             b"license_state".as_ref(),
             &[ctx.bumps.license_state]
         ];
         let license_state_signer = &[&license_state_seeds[..]];
 
-        token::mint_to(
+        token::transfer(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                token::MintTo {
-                    mint: ctx.accounts.mint.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.escrow_token_account.to_account_info(),
                     to: ctx.accounts.token_account.to_account_info(),
                     authority: ctx.accounts.license_state.to_account_info(),
                 },
                 license_state_signer,
             ),
-            1, // Amount: 1 NFT
+            1,
         )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.escrow_token_account.to_account_info(),
+                destination: ctx.accounts.recipient.to_account_info(),
+                authority: ctx.accounts.license_state.to_account_info(),
+            },
+            license_state_signer,
+        ))?;
 
-        // Create NFT metadata using Metaplex
-        let metadata_name = "Brolli".to_string();
-        let metadata_symbol = "BROLLI".to_string();
-        let metadata_uri = if image_uri.is_empty() {
-            DEFAULT_IMAGE_URI.to_string()
-        } else {
-            image_uri.clone()
-        };
+        token::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Approve {
+                    to: ctx.accounts.token_account.to_account_info(),
+                    delegate: ctx.accounts.license_state.to_account_info(),
+                    authority: ctx.accounts.recipient.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+        set_license_token_frozen(
+            LicenseTokenAccounts {
+                license_state: ctx.accounts.license_state.to_account_info(),
+                token_account: ctx.accounts.token_account.to_account_info(),
+                master_edition: ctx.accounts.master_edition.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+            },
+            true,
+            license_state_signer,
+        )?;
 
-        let creators = vec![Creator {
-            address: ctx.accounts.license_state.key(),
-            verified: true,
-            share: 100,
-        }];
+        emit!(LicenseClaimed {
+            mint: ctx.accounts.mint.key(),
+            token_id: ctx.accounts.license_metadata.token_id,
+            owner: ctx.accounts.recipient.key(),
+        });
+        Ok(())
+    }
 
-        let metadata_data = DataV2 {
-            name: metadata_name,
-            symbol: metadata_symbol,
-            uri: metadata_uri,
-            seller_fee_basis_points: 0,
-            creators: Some(creators),
-            collection: None,
-            uses: None,
-        };
+    // Authority-only: withdraw an airdropped license that was never claimed. Burns it
+    // out of escrow, closes its accounts with the rent going to the authority, frees
+    // the recipient to hold a license and gives back its supply slot.
+    pub fn cancel_airdrop(ctx: Context<CancelAirdrop>) -> Result<()> {
+        let license_state_seeds = &[
+            b"license_state".as_ref(),
+            &[ctx.bumps.license_state]
+        ];
+        let license_state_signer = &[&license_state_seeds[..]];
 
-        // Create NFT metadata using Metaplex CPI
-        mpl_token_metadata::instructions::CreateMetadataAccountV3CpiBuilder::new(
+        let license_state_info = ctx.accounts.license_state.to_account_info();
+        let collection_metadata = ctx
+            .accounts
+            .collection_metadata
+            .as_ref()
+            .map(|account| account.to_account_info());
+        mpl_token_metadata::instructions::BurnNftCpiBuilder::new(
             &ctx.accounts.token_metadata_program,
         )
-        .metadata(&ctx.accounts.metadata.to_account_info())
+        .metadata(&ctx.accounts.metadata)
+        .owner(&license_state_info)
         .mint(&ctx.accounts.mint.to_account_info())
-        .mint_authority(&ctx.accounts.license_state.to_account_info())
-        .payer(&ctx.accounts.user.to_account_info())
-        .update_authority(&ctx.accounts.license_state.to_account_info(), true)
-        .system_program(&ctx.accounts.system_program.to_account_info())
-        .rent(Some(&ctx.accounts.rent.to_account_info()))
-        .data(metadata_data)
-        .is_mutable(true)
-        .invoke()?;
+        .token_account(&ctx.accounts.escrow_token_account.to_account_info())
+        .master_edition_account(&ctx.accounts.master_edition)
+        .spl_token_program(&ctx.accounts.token_program)
+        .collection_metadata(collection_metadata.as_ref())
+        .invoke_signed(license_state_signer)?;
 
-        // Store license metadata - use defaults if empty strings provided
-        let license_metadata = &mut ctx.accounts.license_metadata;
-        license_metadata.name = name.clone();
-        license_metadata.image_uri = if image_uri.is_empty() {
-            DEFAULT_IMAGE_URI.to_string()
-        } else {
-            image_uri
-        };
-        license_metadata.provenance_cid = if provenance_cid.is_empty() {
-            DEFAULT_PROVENANCE_CID.to_string()
-        } else {
-            provenance_cid.clone()
-        };
-        license_metadata.owner = ctx.accounts.user.key();
-        license_metadata.mint = ctx.accounts.mint.key();
-        license_metadata.token_id = new_token_id;
+        // Metaplex refunds the burned accounts' rent to their owner, license_state
+        let rent_exempt = Rent::get()?.minimum_balance(license_state_info.data_len());
+        let refund = license_state_info.lamports().saturating_sub(rent_exempt);
+        **license_state_info.try_borrow_mut_lamports()? -= refund;
+        **ctx.accounts.authority.to_account_info().try_borrow_mut_lamports()? += refund;
 
-        emit!(LicenseMinted {
-            user: ctx.accounts.user.key(),
+        // The license never reached its holder, so the slot is freed whatever the burn policy
+        ctx.accounts.license_state.current_supply -= 1;
+
+        emit!(AirdropCancelled {
             mint: ctx.accounts.mint.key(),
-            name,
-            provenance_cid,
-            token_id: new_token_id,
+            token_id: ctx.accounts.license_metadata.token_id,
+            recipient: ctx.accounts.license_metadata.owner,
         });
+        Ok(())
+    }
+
+    // Authority-only, once: mints the sized collection NFT (with a master edition) that
+    // every license is verified into. Both are owned by the license_state PDA.
+    pub fn create_collection(ctx: Context<CreateCollection>) -> Result<()> {
+        let license_state_seeds = &[b"license_state".as_ref(), &[ctx.bumps.license_state]];
+        let license_state_signer = &[&license_state_seeds[..]];
+        let license_state_info = ctx.accounts.license_state.to_account_info();
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.collection_mint.to_account_info(),
+                    to: ctx.accounts.collection_token_account.to_account_info(),
+                    authority: license_state_info.clone(),
+                },
+                license_state_signer,
+            ),
+            1,
+        )?;
+
+        mpl_token_metadata::instructions::CreateMetadataAccountV3CpiBuilder::new(
+            &ctx.accounts.token_metadata_program,
+        )
+        .metadata(&ctx.accounts.collection_metadata)
+        .mint(&ctx.accounts.collection_mint.to_account_info())
+        .mint_authority(&license_state_info)
+        .payer(&ctx.accounts.authority)
+        .update_authority(&license_state_info, true)
+        .system_program(&ctx.accounts.system_program)
+        .rent(Some(&ctx.accounts.rent.to_account_info()))
+        .data(DataV2 {
+            name: ctx.accounts.license_state.name_prefix.clone(),
+            symbol: ctx.accounts.license_state.symbol.clone(),
+            uri: String::new(),
+            seller_fee_basis_points: 0,
+            creators: Some(vec![Creator {
+                address: license_state_info.key(),
+                verified: true,
+                share: 100,
+            }]),
+            collection: None,
+            uses: None,
+        })
+        .is_mutable(true)
+        .collection_details(CollectionDetails::V1 { size: 0 })
+        .invoke_signed(license_state_signer)?;
+
+        mpl_token_metadata::instructions::CreateMasterEditionV3CpiBuilder::new(
+            &ctx.accounts.token_metadata_program,
+        )
+        .edition(&ctx.accounts.collection_master_edition)
+        .mint(&ctx.accounts.collection_mint.to_account_info())
+        .update_authority(&license_state_info)
+        .mint_authority(&license_state_info)
+        .payer(&ctx.accounts.authority)
+        .metadata(&ctx.accounts.collection_metadata)
+        .token_program(&ctx.accounts.token_program)
+        .system_program(&ctx.accounts.system_program)
+        .rent(Some(&ctx.accounts.rent.to_account_info()))
+        .max_supply(0)
+        .invoke_signed(license_state_signer)?;
 
+        let collection_mint = ctx.accounts.collection_mint.key();
+        ctx.accounts.license_state.collection_mint = Some(collection_mint);
+
+        emit!(CollectionCreated { collection_mint });
         Ok(())
     }
 
-    // Authority-only functions to update default URIs
+    // Metadata-editor-only functions to update default URIs
     pub fn update_default_image_uri(ctx: Context<UpdateDefaults>, new_uri: String) -> Result<()> {
-        let license_state = &ctx.accounts.license_state;
-        require!(license_state.authority == ctx.accounts.authority.key(), LicenseError::Unauthorized);
-        // In a real implementation, you would store default URIs in the license state
-        // For now, we'll just emit an event
+        let license_state = &mut ctx.accounts.license_state;
+        validate_license_inputs("", &new_uri, "")?;
+        license_state.default_image_uri = new_uri.clone();
         emit!(DefaultUriUpdated {
             uri_type: "image".to_string(),
             new_uri,
@@ -155,10 +580,11 @@ pub mod brolli_license {
     }
 
     pub fn update_default_provenance_cid(ctx: Context<UpdateDefaults>, new_cid: String) -> Result<()> {
-        let license_state = &ctx.accounts.license_state;
-        require!(license_state.authority == ctx.accounts.authority.key(), LicenseError::Unauthorized);
-        // In a real implementation, you would store default URIs in the license state
-        // For now, we'll just emit an event
+        let license_state = &mut ctx.accounts.license_state;
+        // Mints with an empty provenance fall back to this, so it can't be cleared
+        require!(!new_cid.is_empty(), LicenseError::MissingProvenanceCid);
+        validate_license_inputs("", "", &new_cid)?;
+        license_state.default_provenance_cid = new_cid.clone();
         emit!(DefaultUriUpdated {
             uri_type: "provenance".to_string(),
             new_uri: new_cid,
@@ -166,173 +592,3239 @@ pub mod brolli_license {
         Ok(())
     }
 
-    // Get complete NFT metadata including provenance (similar to Solidity tokenURI)
-    pub fn get_nft_metadata(ctx: Context<GetNftMetadata>) -> Result<NftMetadataResponse> {
-        let license_metadata_account = &ctx.accounts.license_metadata;
+    // Metadata-editor-only: change a license's name, image or provenance and rewrite its
+    // Metaplex metadata to match in the same transaction. None leaves a field as is;
+    // an empty image or provenance resets it to the current default.
+    pub fn update_license_metadata(
+        ctx: Context<UpdateLicenseMetadata>,
+        name: Option<String>,
+        image_uri: Option<String>,
+        provenance_cid: Option<String>,
+    ) -> Result<()> {
+        validate_license_inputs(
+            name.as_deref().unwrap_or_default(),
+            image_uri.as_deref().unwrap_or_default(),
+            provenance_cid.as_deref().unwrap_or_default(),
+        )?;
+
+        let license_metadata = &mut ctx.accounts.license_metadata;
+        if let Some(name) = name {
+            license_metadata.name = name;
+        }
+        // Some("") falls back to the defaults, exactly as it does at mint
+        let (image_uri, provenance_cid) = ctx.accounts.license_state.resolve_uris(
+            image_uri.unwrap_or_else(|| license_metadata.image_uri.clone()),
+            provenance_cid.unwrap_or_else(|| license_metadata.provenance_cid.clone()),
+        );
+        license_metadata.image_uri = image_uri;
+        license_metadata.provenance_cid = provenance_cid;
+
+        // Name, symbol and URI are rewritten; the rest is carried over, so creators and
+        // the verified collection stay exactly as Metaplex recorded them
+        let metadata = Metadata::safe_deserialize(&ctx.accounts.metadata.try_borrow_data()?)
+            .map_err(|_| error!(ErrorCode::AccountDidNotDeserialize))?;
+        let license_state = &ctx.accounts.license_state;
+        let metadata_data = DataV2 {
+            name: license_state.metaplex_name(license_metadata.token_id, &license_metadata.name)?,
+            symbol: license_state.symbol.clone(),
+            uri: license_metadata.image_uri.clone(),
+            seller_fee_basis_points: metadata.seller_fee_basis_points,
+            creators: metadata.creators,
+            collection: metadata.collection,
+            uses: metadata.uses,
+        };
+
+        let license_state_seeds = &[b"license_state".as_ref(), &[ctx.bumps.license_state]];
+        mpl_token_metadata::instructions::UpdateMetadataAccountV2CpiBuilder::new(
+            &ctx.accounts.token_metadata_program,
+        )
+        .metadata(&ctx.accounts.metadata)
+        .update_authority(&ctx.accounts.license_state.to_account_info())
+        .data(metadata_data)
+        .invoke_signed(&[&license_state_seeds[..]])?;
+
+        emit!(LicenseMetadataUpdated {
+            mint: license_metadata.mint,
+            token_id: license_metadata.token_id,
+            name: license_metadata.name.clone(),
+            image_uri: license_metadata.image_uri.clone(),
+            provenance_cid: license_metadata.provenance_cid.clone(),
+        });
+        Ok(())
+    }
+
+    // Metadata-editor-only: branding for new mints. Existing licenses keep their
+    // Metaplex name until update_license_metadata rewrites it.
+    pub fn set_name_and_symbol(
+        ctx: Context<UpdateDefaults>,
+        name_prefix: String,
+        symbol: String,
+    ) -> Result<()> {
+        require!(is_valid_name(&name_prefix), LicenseError::InvalidName);
+        require!(
+            symbol.len() <= mpl_token_metadata::MAX_SYMBOL_LENGTH,
+            LicenseError::SymbolTooLong
+        );
+        let license_state = &mut ctx.accounts.license_state;
+        license_state.name_prefix = name_prefix.clone();
+        license_state.symbol = symbol.clone();
+        // The prefix must leave room for the next token id; each mint then checks
+        // its own name against Metaplex's limit
+        license_state.metaplex_name(license_state.total_minted + 1, "")?;
+
+        emit!(NameAndSymbolUpdated { name_prefix, symbol });
+        Ok(())
+    }
+
+    // Authority-only: open or close tranches without redeploying
+    pub fn set_max_supply(ctx: Context<UpdateLicenseState>, new_max_supply: u64) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        require!(
+            new_max_supply >= license_state.current_supply,
+            LicenseError::MaxSupplyBelowCurrentSupply
+        );
+
+        let old_max_supply = license_state.max_supply;
+        license_state.max_supply = new_max_supply;
+
+        emit!(MaxSupplyUpdated {
+            old_max_supply,
+            new_max_supply,
+        });
+        Ok(())
+    }
+
+    // Authority-only: price in lamports charged by mint_license
+    pub fn set_mint_price(ctx: Context<UpdateLicenseState>, new_mint_price: u64) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+
+        let old_mint_price = license_state.mint_price;
+        license_state.mint_price = new_mint_price;
+
+        emit!(MintPriceUpdated {
+            old_mint_price,
+            new_mint_price,
+        });
+        Ok(())
+    }
+
+    // Authority-only: send accumulated mint proceeds to any recipient,
+    // always leaving the treasury rent-exempt
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        let license_state = &ctx.accounts.license_state;
+
+        // Lamports that auction buyers may still claim back stay in the treasury
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(treasury_info.data_len());
+        let reserved_refunds = license_state.auction.map_or(0, |auction| auction.reserved_refunds());
+        let available = treasury_info
+            .lamports()
+            .saturating_sub(rent_exempt_minimum)
+            .saturating_sub(reserved_refunds);
+        require!(amount <= available, LicenseError::InsufficientTreasuryFunds);
+
+        // Treasury is program-owned, so lamports can be moved directly
+        **treasury_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.recipient.to_account_info().try_borrow_mut_lamports()? += amount;
+
+        emit!(FundsWithdrawn {
+            recipient: ctx.accounts.recipient.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    // Authority-only: charge token_price of payment_mint on every mint, on top of any
+    // lamport mint_price. Creates the treasury's vault ATA for the mint if needed.
+    pub fn set_payment_mint(ctx: Context<SetPaymentMint>, token_price: u64) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+
+        license_state.payment_mint = Some(ctx.accounts.payment_mint.key());
+        license_state.token_price = token_price;
+
+        emit!(PaymentMintUpdated {
+            payment_mint: license_state.payment_mint,
+            token_price,
+        });
+        Ok(())
+    }
+
+    // Authority-only: stop charging an SPL token price
+    pub fn clear_payment_mint(ctx: Context<UpdateLicenseState>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+
+        license_state.payment_mint = None;
+        license_state.token_price = 0;
+
+        emit!(PaymentMintUpdated {
+            payment_mint: None,
+            token_price: 0,
+        });
+        Ok(())
+    }
+
+    // Authority-only: sweep SPL token proceeds from any treasury vault
+    pub fn withdraw_tokens(ctx: Context<WithdrawTokens>, amount: u64) -> Result<()> {
+        let treasury_seeds = &[
+            b"treasury".as_ref(),
+            &[ctx.bumps.treasury]
+        ];
+        let treasury_signer = &[&treasury_seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.vault.to_account_info(),
+                    to: ctx.accounts.recipient_token_account.to_account_info(),
+                    authority: ctx.accounts.treasury.to_account_info(),
+                },
+                treasury_signer,
+            ),
+            amount,
+        )?;
+
+        emit!(TokensWithdrawn {
+            mint: ctx.accounts.vault.mint,
+            recipient_token_account: ctx.accounts.recipient_token_account.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    // Authority-only: start a Dutch auction that replaces mint_price until cleared.
+    // A previous auction can only be replaced once it has no sales or refunds pending.
+    pub fn set_auction(ctx: Context<UpdateLicenseState>, params: AuctionParams) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        require!(
+            license_state.auction.map_or(0, |auction| auction.reserved_refunds()) == 0,
+            LicenseError::AuctionInProgress
+        );
+        require!(
+            params.start_price >= params.floor_price && params.decay_interval > 0,
+            LicenseError::InvalidAuctionConfig
+        );
+
+        license_state.auction = Some(AuctionConfig {
+            start_price: params.start_price,
+            floor_price: params.floor_price,
+            start_time: params.start_time,
+            decay_interval: params.decay_interval,
+            decay_step: params.decay_step,
+            settlement: params.settlement,
+            first_token_id: license_state.total_minted + 1,
+            last_price: 0,
+            sales: 0,
+            proceeds: 0,
+            clearing_price: None,
+            pending_refunds: 0,
+        });
+
+        emit!(AuctionConfigured {
+            start_price: params.start_price,
+            floor_price: params.floor_price,
+            start_time: params.start_time,
+            decay_interval: params.decay_interval,
+            decay_step: params.decay_step,
+            settlement: params.settlement,
+        });
+        Ok(())
+    }
+
+    // Authority-only: go back to flat mint_price pricing
+    pub fn clear_auction(ctx: Context<UpdateLicenseState>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        require!(
+            license_state.auction.map_or(0, |auction| auction.reserved_refunds()) == 0,
+            LicenseError::AuctionInProgress
+        );
+
+        license_state.auction = None;
+        Ok(())
+    }
+
+    // Authority-only: fix the clearing price once the auction sold out or hit its floor.
+    // Later mints pay the clearing price; earlier buyers can claim the difference.
+    pub fn settle_auction(ctx: Context<UpdateLicenseState>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        let sold_out = license_state.current_supply >= license_state.max_supply;
+
+        let auction = license_state.auction.as_mut().ok_or(LicenseError::NoAuction)?;
+        require!(auction.clearing_price.is_none(), LicenseError::AuctionAlreadySettled);
+        let current_price = auction.price_at(Clock::get()?.unix_timestamp);
+        require!(
+            sold_out || current_price == auction.floor_price,
+            LicenseError::AuctionNotEnded
+        );
+
+        // Prices only fall, so the latest sale is the lowest one
+        let clearing_price = if auction.sales > 0 {
+            auction.last_price.min(current_price)
+        } else {
+            current_price
+        };
+        auction.clearing_price = Some(clearing_price);
+        if auction.settlement {
+            auction.pending_refunds = auction.proceeds - clearing_price * auction.sales;
+        }
+
+        emit!(AuctionSettled {
+            clearing_price,
+            sales: auction.sales,
+            pending_refunds: auction.pending_refunds,
+        });
+        Ok(())
+    }
+
+    // Holder-only: reclaim what was paid above the clearing price of a settled auction
+    pub fn claim_auction_refund(ctx: Context<ClaimAuctionRefund>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        let auction = license_state.auction.as_mut().ok_or(LicenseError::NoAuction)?;
+        require!(auction.settlement, LicenseError::NoRefundDue);
+        let clearing_price = auction.clearing_price.ok_or(LicenseError::AuctionNotEnded)?;
+
+        // Only licenses sold by this auction are refundable
+        let holder_receipt = &mut ctx.accounts.holder_receipt;
+        require!(auction.sold(holder_receipt), LicenseError::NoRefundDue);
+        let refund = holder_receipt.price_paid.saturating_sub(clearing_price);
+        require!(refund > 0, LicenseError::NoRefundDue);
+        holder_receipt.price_paid = clearing_price;
+        auction.pending_refunds -= refund;
+
+        // Treasury is program-owned, so lamports can be moved directly
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? -= refund;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += refund;
+
+        emit!(AuctionRefundClaimed {
+            user: ctx.accounts.user.key(),
+            amount: refund,
+        });
+        Ok(())
+    }
+
+    // Authority-only: allowlist Merkle root (keccak leaves of holder keys) and its discount
+    pub fn set_allowlist(
+        ctx: Context<UpdateLicenseState>,
+        merkle_root: [u8; 32],
+        discount_bps: u16,
+    ) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        require!(discount_bps <= 10_000, LicenseError::InvalidDiscount);
+
+        license_state.merkle_root = merkle_root;
+        license_state.allowlist_discount_bps = discount_bps;
+
+        emit!(AllowlistUpdated {
+            merkle_root,
+            discount_bps,
+        });
+        Ok(())
+    }
+
+    // Authority-only: switch between closed, allowlist and public minting
+    pub fn set_mint_phase(ctx: Context<UpdateLicenseState>, phase: MintPhase) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+
+        license_state.phase = phase;
+
+        emit!(MintPhaseUpdated { phase });
+        Ok(())
+    }
+
+    // Authority-only: first step of handing over authority, completed by accept_authority
+    pub fn propose_authority(ctx: Context<UpdateLicenseState>, new_authority: Pubkey) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+
+        license_state.pending_authority = Some(new_authority);
+
+        emit!(AuthorityTransferProposed {
+            authority: license_state.authority,
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    // Authority-only: withdraw a proposal before it is accepted
+    pub fn cancel_authority_transfer(ctx: Context<UpdateLicenseState>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        let pending_authority = license_state
+            .pending_authority
+            .take()
+            .ok_or(LicenseError::NoPendingAuthority)?;
+
+        emit!(AuthorityTransferCancelled {
+            authority: license_state.authority,
+            pending_authority,
+        });
+        Ok(())
+    }
+
+    // Proposed authority only: authority moves once the new key signs
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        let new_authority = ctx.accounts.new_authority.key();
+
+        let previous_authority = license_state.authority;
+        license_state.authority = new_authority;
+        license_state.pending_authority = None;
+
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority,
+        });
+        Ok(())
+    }
+
+    // Authority-only: escape hatch that thaws a holder's token account so the
+    // license can be legitimately reassigned
+    pub fn thaw_for_transfer(ctx: Context<SetLicenseFrozen>) -> Result<()> {
+        require!(
+            ctx.accounts.license_metadata.revoked_at.is_none(),
+            LicenseError::LicenseRevoked
+        );
+
+        let license_state_seeds = &[
+            b"license_state".as_ref(),
+            &[ctx.bumps.license_state]
+        ];
+        let license_state_signer = &[&license_state_seeds[..]];
+
+        set_license_token_frozen(
+            LicenseTokenAccounts {
+                license_state: ctx.accounts.license_state.to_account_info(),
+                token_account: ctx.accounts.token_account.to_account_info(),
+                master_edition: ctx.accounts.master_edition.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+            },
+            false,
+            license_state_signer,
+        )?;
+
+        emit!(LicenseThawed {
+            mint: ctx.accounts.mint.key(),
+            token_account: ctx.accounts.token_account.key(),
+        });
+        Ok(())
+    }
+
+    // Authority-only: lock a license again, e.g. in its new holder's token account
+    pub fn freeze_license(ctx: Context<SetLicenseFrozen>) -> Result<()> {
+        let license_state_seeds = &[
+            b"license_state".as_ref(),
+            &[ctx.bumps.license_state]
+        ];
+        let license_state_signer = &[&license_state_seeds[..]];
+
+        set_license_token_frozen(
+            LicenseTokenAccounts {
+                license_state: ctx.accounts.license_state.to_account_info(),
+                token_account: ctx.accounts.token_account.to_account_info(),
+                master_edition: ctx.accounts.master_edition.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+            },
+            true,
+            license_state_signer,
+        )?;
+
+        emit!(LicenseFrozen {
+            mint: ctx.accounts.mint.key(),
+            token_account: ctx.accounts.token_account.key(),
+        });
+        Ok(())
+    }
+
+    // Holder-only: move a license to a wallet that holds none, keeping
+    // LicenseMetadata.owner and the holder receipts in sync with the token
+    pub fn transfer_license(ctx: Context<TransferLicense>) -> Result<()> {
+        let license_state_seeds = &[
+            b"license_state".as_ref(),
+            &[ctx.bumps.license_state]
+        ];
+        let license_state_signer = &[&license_state_seeds[..]];
+
+        // Already thawed if the authority used thaw_for_transfer
+        if ctx.accounts.from_token_account.is_frozen() {
+            set_license_token_frozen(
+                LicenseTokenAccounts {
+                    license_state: ctx.accounts.license_state.to_account_info(),
+                    token_account: ctx.accounts.from_token_account.to_account_info(),
+                    master_edition: ctx.accounts.master_edition.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                },
+                false,
+                license_state_signer,
+            )?;
+        }
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.from_token_account.to_account_info(),
+                    to: ctx.accounts.to_token_account.to_account_info(),
+                    authority: ctx.accounts.holder.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+
+        token::approve(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Approve {
+                    to: ctx.accounts.to_token_account.to_account_info(),
+                    delegate: ctx.accounts.license_state.to_account_info(),
+                    authority: ctx.accounts.new_owner.to_account_info(),
+                },
+            ),
+            1,
+        )?;
+        set_license_token_frozen(
+            LicenseTokenAccounts {
+                license_state: ctx.accounts.license_state.to_account_info(),
+                token_account: ctx.accounts.to_token_account.to_account_info(),
+                master_edition: ctx.accounts.master_edition.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+            },
+            true,
+            license_state_signer,
+        )?;
+
+        let new_owner = ctx.accounts.new_owner.key();
+        let license_metadata = &mut ctx.accounts.license_metadata;
+        license_metadata.owner = new_owner;
+
+        // The old receipt is closed by its account constraint. What was paid moves with
+        // the license, so an unclaimed auction refund stays claimable by the new owner
+        // and pending_refunds can still reach zero.
+        let to_receipt = &mut ctx.accounts.to_receipt;
+        to_receipt.owner = new_owner;
+        to_receipt.mint = license_metadata.mint;
+        to_receipt.token_id = license_metadata.token_id;
+        to_receipt.price_paid = ctx.accounts.from_receipt.price_paid;
+        to_receipt.auction_sale = ctx.accounts.from_receipt.auction_sale;
+
+        emit!(LicenseTransferred {
+            mint: license_metadata.mint,
+            token_id: license_metadata.token_id,
+            from: ctx.accounts.holder.key(),
+            to: new_owner,
+        });
+        Ok(())
+    }
+
+    // Authority-only: terminate a license for breach. The token stays frozen in the
+    // holder's account (SPL Token only lets the owner or a delegate burn), or in escrow
+    // if it was never claimed, and the license metadata records when and why it was
+    // revoked.
+    pub fn revoke_license(ctx: Context<RevokeLicense>, reason_code: u16) -> Result<()> {
+        let in_escrow = ctx.accounts.token_account.owner == ctx.accounts.license_state.key();
+        if !in_escrow && !ctx.accounts.token_account.is_frozen() {
+            let license_state_seeds = &[
+                b"license_state".as_ref(),
+                &[ctx.bumps.license_state]
+            ];
+            let license_state_signer = &[&license_state_seeds[..]];
+
+            set_license_token_frozen(
+                LicenseTokenAccounts {
+                    license_state: ctx.accounts.license_state.to_account_info(),
+                    token_account: ctx.accounts.token_account.to_account_info(),
+                    master_edition: ctx.accounts.master_edition.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                },
+                true,
+                license_state_signer,
+            )?;
+        }
+
+        let revoked_at = Clock::get()?.unix_timestamp;
+        let license_metadata = &mut ctx.accounts.license_metadata;
+        license_metadata.revoked_at = Some(revoked_at);
+        license_metadata.revocation_reason = reason_code;
+
+        emit!(LicenseRevoked {
+            mint: license_metadata.mint,
+            token_id: license_metadata.token_id,
+            owner: license_metadata.owner,
+            reason_code,
+            revoked_at,
+        });
+        Ok(())
+    }
+
+    // Holder-only: give up a license. Burns the NFT through Metaplex, refunds the rent
+    // of every license account to the holder and frees the address to hold a license
+    // again. Frees a supply slot too when burn_frees_supply is set.
+    pub fn burn_license(ctx: Context<BurnLicense>) -> Result<()> {
+        let license_state_seeds = &[
+            b"license_state".as_ref(),
+            &[ctx.bumps.license_state]
+        ];
+        let license_state_signer = &[&license_state_seeds[..]];
+
+        if ctx.accounts.token_account.is_frozen() {
+            set_license_token_frozen(
+                LicenseTokenAccounts {
+                    license_state: ctx.accounts.license_state.to_account_info(),
+                    token_account: ctx.accounts.token_account.to_account_info(),
+                    master_edition: ctx.accounts.master_edition.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                    token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                },
+                false,
+                license_state_signer,
+            )?;
+        }
+
+        if ctx.accounts.master_edition.data_is_empty() {
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Burn {
+                        mint: ctx.accounts.mint.to_account_info(),
+                        from: ctx.accounts.token_account.to_account_info(),
+                        authority: ctx.accounts.holder.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+
+            token::close_account(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::CloseAccount {
+                    account: ctx.accounts.token_account.to_account_info(),
+                    destination: ctx.accounts.holder.to_account_info(),
+                    authority: ctx.accounts.holder.to_account_info(),
+                },
+            ))?;
+        } else {
+            // Burns the token and closes the token account, Metaplex metadata and master
+            // edition, taking the license out of the collection's size
+            let collection_metadata = ctx
+                .accounts
+                .collection_metadata
+                .as_ref()
+                .map(|account| account.to_account_info());
+            mpl_token_metadata::instructions::BurnNftCpiBuilder::new(
+                &ctx.accounts.token_metadata_program,
+            )
+            .metadata(&ctx.accounts.metadata)
+            .owner(&ctx.accounts.holder)
+            .mint(&ctx.accounts.mint.to_account_info())
+            .token_account(&ctx.accounts.token_account.to_account_info())
+            .master_edition_account(&ctx.accounts.master_edition)
+            .spl_token_program(&ctx.accounts.token_program)
+            .collection_metadata(collection_metadata.as_ref())
+            .invoke()?;
+        }
+
+        // license_metadata, license_terms and holder_receipt are closed by their account
+        // constraints, so settle the receipt's share of any auction refund first
+        let license_state = &mut ctx.accounts.license_state;
+        let auction_refund = license_state
+            .auction
+            .as_mut()
+            .map_or(0, |auction| auction.remove_sale(&ctx.accounts.holder_receipt));
+        if auction_refund > 0 {
+            **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? -= auction_refund;
+            **ctx.accounts.holder.to_account_info().try_borrow_mut_lamports()? += auction_refund;
+        }
+
+        let supply_freed = license_state.burn_frees_supply;
+        if supply_freed {
+            license_state.current_supply -= 1;
+        }
+
+        emit!(LicenseBurned {
+            mint: ctx.accounts.mint.key(),
+            token_id: ctx.accounts.license_metadata.token_id,
+            owner: ctx.accounts.holder.key(),
+            supply_freed,
+            auction_refund,
+        });
+        Ok(())
+    }
+
+    // Authority-only: choose whether burned licenses free a supply slot
+    pub fn set_burn_policy(ctx: Context<UpdateLicenseState>, burn_frees_supply: bool) -> Result<()> {
+        ctx.accounts.license_state.burn_frees_supply = burn_frees_supply;
+
+        emit!(BurnPolicyUpdated { burn_frees_supply });
+        Ok(())
+    }
+
+    // Authority-only: validity period given to new licenses and added by each renewal.
+    // None keeps licenses perpetual.
+    pub fn set_license_terms(
+        ctx: Context<UpdateLicenseState>,
+        license_duration: Option<i64>,
+        renewal_price: u64,
+    ) -> Result<()> {
+        require!(
+            license_duration.unwrap_or(1) > 0,
+            LicenseError::InvalidLicenseDuration
+        );
+
+        let license_state = &mut ctx.accounts.license_state;
+        license_state.license_duration = license_duration;
+        license_state.renewal_price = renewal_price;
+
+        emit!(LicenseTermsUpdated {
+            license_duration,
+            renewal_price,
+        });
+        Ok(())
+    }
+
+    // Anyone can pay renewal_price into the treasury to extend a time-bound license by
+    // license_duration, counted from its expiry or from now if it already lapsed
+    pub fn renew_license(ctx: Context<RenewLicense>) -> Result<()> {
+        let license_state = &ctx.accounts.license_state;
+        let license_duration = license_state
+            .license_duration
+            .ok_or(LicenseError::LicenseNotRenewable)?;
+        let valid_until = ctx
+            .accounts
+            .license_metadata
+            .valid_until
+            .ok_or(LicenseError::LicenseNotRenewable)?;
+
+        let renewal_price = license_state.renewal_price;
+        if renewal_price > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: ctx.accounts.treasury.to_account_info(),
+                    },
+                ),
+                renewal_price,
+            )?;
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        let valid_until = valid_until
+            .max(now)
+            .checked_add(license_duration)
+            .ok_or(LicenseError::LicenseValidityOverflow)?;
+        let license_metadata = &mut ctx.accounts.license_metadata;
+        license_metadata.valid_until = Some(valid_until);
+
+        emit!(LicenseRenewed {
+            mint: license_metadata.mint,
+            token_id: license_metadata.token_id,
+            valid_until,
+            price: renewal_price,
+        });
+        Ok(())
+    }
+
+    // Pauser-only: halt minting and metadata updates during an incident
+    pub fn pause(ctx: Context<SetPaused>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        require!(!license_state.paused, LicenseError::Paused);
+        license_state.paused = true;
+
+        emit!(ProgramPaused {
+            pauser: ctx.accounts.pauser.key(),
+        });
+        Ok(())
+    }
+
+    // Pauser-only: resume minting and metadata updates
+    pub fn unpause(ctx: Context<SetPaused>) -> Result<()> {
+        let license_state = &mut ctx.accounts.license_state;
+        require!(license_state.paused, LicenseError::NotPaused);
+        license_state.paused = false;
+
+        emit!(ProgramUnpaused {
+            pauser: ctx.accounts.pauser.key(),
+        });
+        Ok(())
+    }
+
+    // Admin-only: give a member a role, recorded as a role PDA
+    pub fn grant_role(ctx: Context<GrantRole>, role: Role, member: Pubkey) -> Result<()> {
+        let role_assignment = &mut ctx.accounts.role_assignment;
+        role_assignment.role = role;
+        role_assignment.member = member;
+
+        emit!(RoleGranted { role, member });
+        Ok(())
+    }
+
+    // Admin-only: close a member's role PDA, refunding rent to the admin
+    pub fn revoke_role(_ctx: Context<RevokeRole>, role: Role, member: Pubkey) -> Result<()> {
+        emit!(RoleRevoked { role, member });
+        Ok(())
+    }
+
+    // One-off upgrade of a license_state created by the original program version.
+    // Resizes the account to the current layout, seeds the defaults from the constants and
+    // turns every legacy has_license entry into a holder receipt. remaining_accounts must
+    // hold the receipt PDA for each legacy holder, in has_license order.
+    pub fn migrate_license_state<'info>(
+        ctx: Context<'_, '_, '_, 'info, MigrateLicenseState<'info>>,
+    ) -> Result<()> {
+        let license_state_info = ctx.accounts.license_state.to_account_info();
+        let authority_info = ctx.accounts.authority.to_account_info();
+        let system_program_info = ctx.accounts.system_program.to_account_info();
+
+        let legacy = {
+            let data = license_state_info.try_borrow_data()?;
+            require!(
+                data.len() == 8 + LegacyLicenseState::SPACE,
+                LicenseError::AlreadyMigrated
+            );
+            require!(
+                data[..8] == <LicenseState as anchor_lang::Discriminator>::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            LegacyLicenseState::deserialize(&mut &data[8..])?
+        };
+        require!(legacy.authority == authority_info.key(), LicenseError::Unauthorized);
+        require!(
+            ctx.remaining_accounts.len() == legacy.has_license.len(),
+            ErrorCode::AccountNotEnoughKeys
+        );
+
+        // Legacy holders were pushed in mint order, so entry i holds token id i + 1
+        for (index, (holder, receipt_info)) in legacy
+            .has_license
+            .iter()
+            .zip(ctx.remaining_accounts.iter())
+            .enumerate()
+        {
+            let token_id = index as u64 + 1;
+            let (receipt_key, receipt_bump) =
+                Pubkey::find_program_address(&[b"holder", holder.as_ref()], &crate::ID);
+            require_keys_eq!(receipt_info.key(), receipt_key, ErrorCode::ConstraintSeeds);
+            let (mint, _) =
+                Pubkey::find_program_address(&[b"mint", token_id.to_le_bytes().as_ref()], &crate::ID);
+
+            create_pda_account(
+                &authority_info,
+                receipt_info,
+                &system_program_info,
+                8 + HolderReceipt::SPACE,
+                &crate::ID,
+                &[b"holder", holder.as_ref(), &[receipt_bump]],
+            )?;
+            let receipt = HolderReceipt {
+                owner: *holder,
+                mint,
+                token_id,
+                price_paid: 0,
+                auction_sale: false,
+            };
+            let mut data = receipt_info.try_borrow_mut_data()?;
+            receipt.try_serialize(&mut &mut data[..])?;
+        }
+
+        let license_state = LicenseState {
+            authority: legacy.authority,
+            current_supply: legacy.current_supply,
+            max_supply: legacy.max_supply,
+            default_image_uri: String::new(),
+            default_provenance_cid: DEFAULT_PROVENANCE_CID.to_string(),
+            mint_price: 0,
+            payment_mint: None,
+            token_price: 0,
+            auction: None,
+            phase: MintPhase::Public,
+            merkle_root: [0; 32],
+            allowlist_discount_bps: 0,
+            pending_authority: None,
+            paused: false,
+            total_minted: legacy.current_supply,
+            burn_frees_supply: false,
+            license_duration: None,
+            renewal_price: 0,
+            collection_mint: None,
+            name_prefix: DEFAULT_NAME_PREFIX.to_string(),
+            symbol: DEFAULT_SYMBOL.to_string(),
+        };
+
+        // The original program version had no treasury
+        let (_, treasury_bump) = Pubkey::find_program_address(&[b"treasury"], &crate::ID);
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        create_pda_account(
+            &authority_info,
+            &treasury_info,
+            &system_program_info,
+            8 + Treasury::SPACE,
+            &crate::ID,
+            &[b"treasury", &[treasury_bump]],
+        )?;
+        Treasury {}.try_serialize(&mut &mut treasury_info.try_borrow_mut_data()?[..])?;
+
+        // Settle rent for the new size before resizing
+        let new_space = 8 + LicenseState::SPACE;
+        let rent_needed = Rent::get()?.minimum_balance(new_space);
+        let current_lamports = license_state_info.lamports();
+        if rent_needed > current_lamports {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program_info,
+                    anchor_lang::system_program::Transfer {
+                        from: authority_info,
+                        to: license_state_info.clone(),
+                    },
+                ),
+                rent_needed - current_lamports,
+            )?;
+        } else {
+            let excess = current_lamports - rent_needed;
+            **license_state_info.try_borrow_mut_lamports()? -= excess;
+            **authority_info.try_borrow_mut_lamports()? += excess;
+        }
+        license_state_info.realloc(new_space, false)?;
+
+        let mut data = license_state_info.try_borrow_mut_data()?;
+        license_state.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    // Get complete NFT metadata including provenance (similar to Solidity tokenURI)
+    pub fn get_nft_metadata(ctx: Context<GetNftMetadata>) -> Result<NftMetadataResponse> {
+        let license_metadata_account = &ctx.accounts.license_metadata;
+
+        Ok(NftMetadataResponse {
+            name: ctx
+                .accounts
+                .license_state
+                .display_name(license_metadata_account.token_id, &license_metadata_account.name),
+            description: "For BUIDLers".to_string(),
+            image: license_metadata_account.image_uri.clone(),
+            provenance_cid: license_metadata_account.provenance_cid.clone(),
+            token_id: license_metadata_account.token_id,
+            owner: license_metadata_account.owner,
+            mint: license_metadata_account.mint,
+            revoked: license_metadata_account.revoked_at.is_some(),
+            revoked_at: license_metadata_account.revoked_at,
+            revocation_reason: license_metadata_account.revocation_reason,
+            valid_from: license_metadata_account.valid_from,
+            valid_until: license_metadata_account.valid_until,
+            terms: ctx
+                .accounts
+                .license_terms
+                .as_ref()
+                .map(|license_terms| license_terms.terms.clone()),
+        })
+    }
+
+    // tokenURI equivalent: Metaplex-standard JSON as a data:application/json;base64 URI,
+    // matching Brolli.sol so wallets and indexers can render without an off-chain server.
+    // Licenses without a hosted image embed the on-chain SVG as their image.
+    pub fn get_token_uri(ctx: Context<GetNftMetadata>) -> Result<String> {
+        let license_metadata_account = &ctx.accounts.license_metadata;
+        let image = if license_metadata_account.image_uri.is_empty() {
+            svg_data_uri(&render_license_svg(
+                license_metadata_account,
+                ctx.accounts.license_terms.as_deref(),
+            ))
+        } else {
+            license_metadata_account.image_uri.clone()
+        };
+        let token_uri = build_token_uri(
+            &ctx.accounts
+                .license_state
+                .display_name(license_metadata_account.token_id, &license_metadata_account.name),
+            &image,
+            &license_metadata_account.provenance_cid,
+        );
+
+        // Borsh String = 4-byte length prefix + bytes, all of which must fit in return data
+        require!(
+            4 + token_uri.len() <= MAX_RETURN_DATA,
+            LicenseError::TokenUriTooLarge
+        );
+
+        Ok(token_uri)
+    }
+
+    // The license artwork as raw SVG, rendered entirely on-chain
+    pub fn get_license_svg(ctx: Context<GetNftMetadata>) -> Result<String> {
+        let svg = render_license_svg(
+            &ctx.accounts.license_metadata,
+            ctx.accounts.license_terms.as_deref(),
+        );
+        require!(4 + svg.len() <= MAX_RETURN_DATA, LicenseError::TokenUriTooLarge);
+        Ok(svg)
+    }
+
+    // Whether the license is in force right now - not revoked and inside its validity
+    // window. Exposed as return data so downstream programs can gate on it via CPI.
+    pub fn is_license_valid(ctx: Context<GetNftMetadata>) -> Result<bool> {
+        let now = Clock::get()?.unix_timestamp;
+        Ok(ctx.accounts.license_metadata.is_valid_at(now))
+    }
+
+    // Get provenance trait information specifically
+    pub fn get_provenance_trait(ctx: Context<GetNftMetadata>) -> Result<ProvenanceTrait> {
+        let license_metadata_account = &ctx.accounts.license_metadata;
+
+        Ok(ProvenanceTrait {
+            trait_type: "Provenance CID".to_string(),
+            value: license_metadata_account.provenance_cid.clone(),
+        })
+    }
+
+    // Note: Supply info and license check are available by reading license_state account off-chain
+    // No need for special instructions - just fetch the account data directly
+}
+
+// Encodes the Metaplex-standard JSON for a license as a base64 data URI
+fn build_token_uri(display_name: &str, image: &str, provenance_cid: &str) -> String {
+    let json = format!(
+        "{{\"name\":\"{}\",\"description\":\"For BUIDLers\",\"image\":\"{}\",\"attributes\":[{{\"trait_type\":\"Provenance CID\",\"value\":\"{}\"}}]}}",
+        json_escape(display_name),
+        json_escape(image),
+        json_escape(provenance_cid),
+    );
+    format!(
+        "data:application/json;base64,{}",
+        general_purpose::STANDARD.encode(json)
+    )
+}
+
+// Draws the token id, holder name, patent number and expiry onto a card whose colors
+// are derived from the mint, so each license renders the same way every time. Kept
+// minimal so that, embedded in get_token_uri, the worst case fits in return data.
+fn render_license_svg(
+    license_metadata: &LicenseMetadata,
+    license_terms: Option<&LicenseTermsAccount>,
+) -> String {
+    // Background is kept dark enough for the light text
+    let seed = keccak::hash(license_metadata.mint.as_ref()).to_bytes();
+    let background = (seed[0] / 2, seed[1] / 2, seed[2] / 2);
+    let accent = (seed[3] | 0xc0, seed[4] | 0xc0, seed[5] | 0xc0);
+
+    let patent_number = license_terms
+        .map(|license_terms| license_terms.terms.patent_number.as_str())
+        .filter(|patent_number| !patent_number.is_empty())
+        .unwrap_or("-");
+    let expiry = license_metadata
+        .valid_until
+        .map_or_else(|| "Perpetual".to_string(), format_date);
+
+    format!(
+        "<svg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 60 60' font-size='4' fill='#{:02x}{:02x}{:02x}'>\
+<rect width='60' height='60' fill='#{:02x}{:02x}{:02x}'/>\
+<text x='4' y='24' font-size='6'>#{}</text>\
+<text x='4' y='34'>{}</text>\
+<text x='4' y='42'>{}</text>\
+<text x='4' y='50'>{}</text>\
+</svg>",
+        accent.0,
+        accent.1,
+        accent.2,
+        background.0,
+        background.1,
+        background.2,
+        license_metadata.token_id,
+        xml_escape(&license_metadata.name),
+        xml_escape(patent_number),
+        expiry,
+    )
+}
+
+// Embeds an SVG as a utf8 data URI. '#' would otherwise start the URI fragment.
+fn svg_data_uri(svg: &str) -> String {
+    format!(
+        "data:image/svg+xml;utf8,{}",
+        svg.replace('%', "%25").replace('#', "%23")
+    )
+}
+
+// Unix timestamp to YYYY-MM-DD (UTC), using the days-to-civil conversion
+fn format_date(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Escapes a string for embedding as SVG text content. Quotes only matter inside
+// attribute values, and no caller-supplied value is ever put in one.
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;")
+}
+
+// Length and format checks for caller-supplied license fields, run before any CPI.
+// Empty image and provenance values are allowed - they fall back to the defaults.
+fn validate_license_inputs(name: &str, image_uri: &str, provenance_cid: &str) -> Result<()> {
+    require!(name.len() <= MAX_NAME_LENGTH, LicenseError::NameTooLong);
+    require!(is_valid_name(name), LicenseError::InvalidName);
+    require!(image_uri.len() <= MAX_URI_LENGTH, LicenseError::UriTooLong);
+    require!(provenance_cid.len() <= MAX_URI_LENGTH, LicenseError::UriTooLong);
+    require!(
+        is_valid_uri(image_uri) && is_valid_uri(provenance_cid),
+        LicenseError::InvalidUri
+    );
+    require!(
+        provenance_cid.is_empty() || is_valid_provenance(provenance_cid),
+        LicenseError::InvalidCid
+    );
+    Ok(())
+}
+
+// Names are plain text. They end up in generated JSON, SVG and data URIs, so
+// control characters and anything those would have to escape are rejected.
+fn is_valid_name(name: &str) -> bool {
+    !name
+        .chars()
+        .any(|c| c.is_control() || matches!(c, '"' | '\\' | '<' | '&' | '#' | '%'))
+}
+
+// Printable ASCII with nothing that needs escaping in JSON
+fn is_valid_uri(uri: &str) -> bool {
+    uri.bytes().all(|b| b.is_ascii_graphic() && b != b'"' && b != b'\\')
+}
+
+// Accepts a bare CID, an ipfs:// URI, or an http(s) gateway URL in either path
+// (https://gateway/ipfs/<cid>) or subdomain (https://<cid>.ipfs.gateway) form
+fn is_valid_provenance(provenance: &str) -> bool {
+    if let Some(cid) = provenance.strip_prefix("ipfs://") {
+        return is_valid_cid(cid.split('/').next().unwrap_or_default());
+    }
+    let Some(url) = provenance
+        .strip_prefix("https://")
+        .or_else(|| provenance.strip_prefix("http://"))
+    else {
+        return is_valid_cid(provenance);
+    };
+
+    if let Some((_, path)) = url.split_once("/ipfs/") {
+        let cid = path.split(['/', '?', '#']).next().unwrap_or_default();
+        return is_valid_cid(cid);
+    }
+    let host = url.split(['/', '?', '#']).next().unwrap_or_default();
+    match host.split_once(".ipfs.") {
+        Some((cid, gateway)) => !gateway.is_empty() && is_valid_cid(cid),
+        None => false,
+    }
+}
+
+// Syntactic CID check: CIDv0 is 46 base58btc characters starting "Qm"; CIDv1 is a
+// multibase string whose decoded first byte is the version, 0x01
+fn is_valid_cid(cid: &str) -> bool {
+    const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+    const MIN_CIDV1_LENGTH: usize = 10;
+
+    if cid.len() == 46 && cid.starts_with("Qm") {
+        return cid.chars().all(|c| BASE58_ALPHABET.contains(c));
+    }
+    if cid.len() < MIN_CIDV1_LENGTH {
+        return false;
+    }
+    let (multibase, encoded) = cid.split_at(1);
+    match multibase {
+        // base32 lower - 0x01 encodes to a leading 'a'
+        "b" => {
+            encoded.starts_with('a')
+                && encoded.chars().all(|c| c.is_ascii_lowercase() || ('2'..='7').contains(&c))
+        }
+        // base16 lower
+        "f" => {
+            encoded.starts_with("01")
+                && encoded.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        }
+        // base58btc
+        "z" => encoded.chars().all(|c| BASE58_ALPHABET.contains(c)),
+        _ => false,
+    }
+}
+
+// Escapes a string for embedding inside a JSON string literal
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Shared by mint_license and mint_license_allowlisted once the phase checks pass.
+// Allowlisted mints pay the discounted flat price and bypass any Dutch auction.
+fn process_mint_license(
+    ctx: Context<MintLicense>,
+    name: String,
+    image_uri: String,
+    provenance_cid: String,
+    terms: LicenseTerms,
+    allowlist_discount_bps: Option<u16>,
+) -> Result<()> {
+    validate_license_inputs(&name, &image_uri, &provenance_cid)?;
+    terms.validate()?;
+
+    // Check supply limit first - one license per address is enforced by the
+    // holder_receipt account constraint
+    let new_token_id = ctx.accounts.license_state.next_token_id()?;
+    let metadata_name = ctx.accounts.license_state.metaplex_name(new_token_id, &name)?;
+
+    let holder_receipt = &mut ctx.accounts.holder_receipt;
+    holder_receipt.owner = ctx.accounts.user.key();
+    holder_receipt.mint = ctx.accounts.mint.key();
+    holder_receipt.token_id = new_token_id;
+
+    // Collect the mint price into the treasury - a configured Dutch auction
+    // replaces the flat mint_price with its current price
+    let (mint_price, auction_sale) = {
+        let license_state = &mut ctx.accounts.license_state;
+        match (allowlist_discount_bps, license_state.auction.as_mut()) {
+            (Some(discount_bps), _) => (apply_discount(license_state.mint_price, discount_bps), false),
+            (None, Some(auction)) => {
+                let now = Clock::get()?.unix_timestamp;
+                require!(now >= auction.start_time, LicenseError::AuctionNotStarted);
+                let price = auction.price_at(now);
+                auction.record_sale(price);
+                (price, true)
+            }
+            (None, None) => (license_state.mint_price, false),
+        }
+    };
+    // Only auction sales are counted in pending_refunds, so only they may claim one
+    ctx.accounts.holder_receipt.price_paid = mint_price;
+    ctx.accounts.holder_receipt.auction_sale = auction_sale;
+    if mint_price > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                },
+            ),
+            mint_price,
+        )?;
+    }
+
+    // Collect the SPL token price into the treasury vault when a payment mint is set
+    if let Some(payment_mint) = ctx.accounts.license_state.payment_mint {
+        let token_price = apply_discount(
+            ctx.accounts.license_state.token_price,
+            allowlist_discount_bps.unwrap_or(0),
+        );
+        let (payer_token_account, vault) = match (
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.vault,
+        ) {
+            (Some(payer_token_account), Some(vault)) => (payer_token_account, vault),
+            _ => return err!(LicenseError::MissingPaymentAccounts),
+        };
+        require_keys_eq!(
+            vault.key(),
+            get_associated_token_address(&ctx.accounts.treasury.key(), &payment_mint),
+            LicenseError::InvalidVault
+        );
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: payer_token_account.to_account_info(),
+                    to: vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            token_price,
+        )?;
+    }
+
+    let (image_uri, provenance_cid) = ctx
+        .accounts
+        .license_state
+        .resolve_uris(image_uri, provenance_cid);
+    let collection = license_collection(
+        &ctx.accounts.license_state,
+        ctx.accounts.collection_mint.as_ref().map(|account| account.to_account_info()),
+        ctx.accounts.collection_metadata.as_ref().map(|account| account.to_account_info()),
+        ctx.accounts.collection_master_edition.as_ref().map(|account| account.to_account_info()),
+    )?;
+
+    mint_license_nft(
+        LicenseNftAccounts {
+            license_state: ctx.accounts.license_state.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            metadata: ctx.accounts.metadata.to_account_info(),
+            master_edition: ctx.accounts.master_edition.to_account_info(),
+            token_account: ctx.accounts.token_account.to_account_info(),
+            holder: Some(ctx.accounts.user.to_account_info()),
+            payer: ctx.accounts.user.to_account_info(),
+            rent: ctx.accounts.rent.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+            collection,
+        },
+        ctx.bumps.license_state,
+        metadata_name,
+        ctx.accounts.license_state.symbol.clone(),
+        image_uri.clone(),
+    )?;
+
+    // Store license metadata - defaults already applied for empty strings
+    let license_metadata = &mut ctx.accounts.license_metadata;
+    license_metadata.name = name.clone();
+    license_metadata.image_uri = image_uri;
+    license_metadata.provenance_cid = provenance_cid.clone();
+    license_metadata.owner = ctx.accounts.user.key();
+    license_metadata.mint = ctx.accounts.mint.key();
+    license_metadata.token_id = new_token_id;
+    (license_metadata.valid_from, license_metadata.valid_until) = ctx
+        .accounts
+        .license_state
+        .validity_window(Clock::get()?.unix_timestamp)?;
+
+    let license_terms = &mut ctx.accounts.license_terms;
+    license_terms.mint = ctx.accounts.mint.key();
+    license_terms.terms = terms;
+
+    emit!(LicenseMinted {
+        user: ctx.accounts.user.key(),
+        mint: ctx.accounts.mint.key(),
+        name,
+        provenance_cid,
+        token_id: new_token_id,
+    });
+
+    Ok(())
+}
+
+// Accounts used to mint a license NFT and create its Metaplex metadata
+struct LicenseNftAccounts<'info> {
+    license_state: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    metadata: AccountInfo<'info>,
+    master_edition: AccountInfo<'info>,
+    token_account: AccountInfo<'info>,
+    // Signing holder, who approves license_state as delegate. None for airdrops, which
+    // mint into license_state's own escrow account until claim_license.
+    holder: Option<AccountInfo<'info>>,
+    payer: AccountInfo<'info>,
+    rent: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    token_metadata_program: AccountInfo<'info>,
+    collection: Option<CollectionAccounts<'info>>,
+}
+
+struct CollectionAccounts<'info> {
+    mint: AccountInfo<'info>,
+    metadata: AccountInfo<'info>,
+    master_edition: AccountInfo<'info>,
+}
+
+// The collection accounts a new license must be verified into, or None before
+// create_collection has run. Metaplex checks the metadata and edition derivations.
+fn license_collection<'info>(
+    license_state: &LicenseState,
+    mint: Option<AccountInfo<'info>>,
+    metadata: Option<AccountInfo<'info>>,
+    master_edition: Option<AccountInfo<'info>>,
+) -> Result<Option<CollectionAccounts<'info>>> {
+    let Some(collection_mint) = license_state.collection_mint else {
+        return Ok(None);
+    };
+    let (Some(mint), Some(metadata), Some(master_edition)) = (mint, metadata, master_edition) else {
+        return err!(LicenseError::CollectionAccountsMissing);
+    };
+    require_keys_eq!(mint.key(), collection_mint, LicenseError::InvalidCollection);
+    Ok(Some(CollectionAccounts {
+        mint,
+        metadata,
+        master_edition,
+    }))
+}
+
+// Mints one token into the holder's token account and creates its Metaplex metadata
+// and master edition, verified into the Brolli collection when there is one, all
+// signed by the license_state PDA. The edition takes over mint and freeze authority,
+// so the token is frozen through Metaplex with license_state as its delegate. Airdrop
+// recipients can't approve the delegate, so their token waits unfrozen in an account
+// only license_state controls until they claim it.
+fn mint_license_nft(
+    accounts: LicenseNftAccounts,
+    license_state_bump: u8,
+    metadata_name: String,
+    metadata_symbol: String,
+    metadata_uri: String,
+) -> Result<()> {
+    // Mint the NFT
+    let license_state_seeds = &[
+        b"license_state".as_ref(),
+        &[license_state_bump]
+    ];
+    let license_state_signer = &[&license_state_seeds[..]];
+
+    token::mint_to(
+        CpiContext::new_with_signer(
+            accounts.token_program.clone(),
+            token::MintTo {
+                mint: accounts.mint.clone(),
+                to: accounts.token_account.clone(),
+                authority: accounts.license_state.clone(),
+            },
+            license_state_signer,
+        ),
+        1, // Amount: 1 NFT
+    )?;
+
+    // Licenses are non-transferable - the holder's token account is locked once the
+    // edition exists, with license_state as delegate
+    if let Some(holder) = &accounts.holder {
+        token::approve(
+            CpiContext::new(
+                accounts.token_program.clone(),
+                token::Approve {
+                    to: accounts.token_account.clone(),
+                    delegate: accounts.license_state.clone(),
+                    authority: holder.clone(),
+                },
+            ),
+            1,
+        )?;
+    }
+
+    // Create NFT metadata using Metaplex
+    let creators = vec![Creator {
+        address: accounts.license_state.key(),
+        verified: true,
+        share: 100,
+    }];
+
+    let metadata_data = DataV2 {
+        name: metadata_name,
+        symbol: metadata_symbol,
+        uri: metadata_uri,
+        seller_fee_basis_points: 0,
+        creators: Some(creators),
+        collection: accounts.collection.as_ref().map(|collection| Collection {
+            verified: false,
+            key: collection.mint.key(),
+        }),
+        uses: None,
+    };
+
+    // Create NFT metadata using Metaplex CPI
+    mpl_token_metadata::instructions::CreateMetadataAccountV3CpiBuilder::new(
+        &accounts.token_metadata_program,
+    )
+    .metadata(&accounts.metadata)
+    .mint(&accounts.mint)
+    .mint_authority(&accounts.license_state)
+    .payer(&accounts.payer)
+    .update_authority(&accounts.license_state, true)
+    .system_program(&accounts.system_program)
+    .rent(Some(&accounts.rent))
+    .data(metadata_data)
+    .is_mutable(true)
+    .invoke_signed(license_state_signer)?;
+
+    // Max supply 0 makes this a 1/1 - no prints can ever be minted
+    mpl_token_metadata::instructions::CreateMasterEditionV3CpiBuilder::new(
+        &accounts.token_metadata_program,
+    )
+    .edition(&accounts.master_edition)
+    .mint(&accounts.mint)
+    .update_authority(&accounts.license_state)
+    .mint_authority(&accounts.license_state)
+    .payer(&accounts.payer)
+    .metadata(&accounts.metadata)
+    .token_program(&accounts.token_program)
+    .system_program(&accounts.system_program)
+    .rent(Some(&accounts.rent))
+    .max_supply(0)
+    .invoke_signed(license_state_signer)?;
+
+    if accounts.holder.is_some() {
+        set_license_token_frozen(
+            LicenseTokenAccounts {
+                license_state: accounts.license_state.clone(),
+                token_account: accounts.token_account.clone(),
+                master_edition: accounts.master_edition.clone(),
+                mint: accounts.mint.clone(),
+                token_program: accounts.token_program.clone(),
+                token_metadata_program: accounts.token_metadata_program.clone(),
+            },
+            true,
+            license_state_signer,
+        )?;
+    }
+
+    if let Some(collection) = &accounts.collection {
+        mpl_token_metadata::instructions::VerifySizedCollectionItemCpiBuilder::new(
+            &accounts.token_metadata_program,
+        )
+        .metadata(&accounts.metadata)
+        .collection_authority(&accounts.license_state)
+        .payer(&accounts.payer)
+        .collection_mint(&collection.mint)
+        .collection(&collection.metadata)
+        .collection_master_edition_account(&collection.master_edition)
+        .invoke_signed(license_state_signer)?;
+    }
+
+    Ok(())
+}
+
+struct LicenseTokenAccounts<'info> {
+    license_state: AccountInfo<'info>,
+    token_account: AccountInfo<'info>,
+    master_edition: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    token_metadata_program: AccountInfo<'info>,
+}
+
+// Freezes or thaws a license token account through Metaplex, which holds the freeze
+// authority via the master edition. license_state signs as the account's delegate.
+// Licenses minted before master editions have none, so license_state is still their
+// mint's freeze authority and freezes them directly.
+fn set_license_token_frozen(
+    accounts: LicenseTokenAccounts,
+    frozen: bool,
+    license_state_signer: &[&[&[u8]]],
+) -> Result<()> {
+    if accounts.master_edition.data_is_empty() {
+        if frozen {
+            token::freeze_account(CpiContext::new_with_signer(
+                accounts.token_program,
+                token::FreezeAccount {
+                    account: accounts.token_account,
+                    mint: accounts.mint,
+                    authority: accounts.license_state,
+                },
+                license_state_signer,
+            ))?;
+        } else {
+            token::thaw_account(CpiContext::new_with_signer(
+                accounts.token_program,
+                token::ThawAccount {
+                    account: accounts.token_account,
+                    mint: accounts.mint,
+                    authority: accounts.license_state,
+                },
+                license_state_signer,
+            ))?;
+        }
+        return Ok(());
+    }
+
+    // A new holder must first approve license_state as delegate
+    let token_account = TokenAccount::try_deserialize(&mut &accounts.token_account.try_borrow_data()?[..])?;
+    require_license_delegate(&token_account, accounts.license_state.key())?;
+    if frozen {
+        mpl_token_metadata::instructions::FreezeDelegatedAccountCpiBuilder::new(
+            &accounts.token_metadata_program,
+        )
+        .delegate(&accounts.license_state)
+        .token_account(&accounts.token_account)
+        .edition(&accounts.master_edition)
+        .mint(&accounts.mint)
+        .token_program(&accounts.token_program)
+        .invoke_signed(license_state_signer)?;
+    } else {
+        mpl_token_metadata::instructions::ThawDelegatedAccountCpiBuilder::new(
+            &accounts.token_metadata_program,
+        )
+        .delegate(&accounts.license_state)
+        .token_account(&accounts.token_account)
+        .edition(&accounts.master_edition)
+        .mint(&accounts.mint)
+        .token_program(&accounts.token_program)
+        .invoke_signed(license_state_signer)?;
+    }
+    Ok(())
+}
+
+// license_state can only freeze or thaw accounts whose holder approved it as delegate
+fn require_license_delegate(token_account: &TokenAccount, license_state: Pubkey) -> Result<()> {
+    require!(
+        token_account.delegate == COption::Some(license_state),
+        LicenseError::LicenseNotDelegated
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + LicenseState::SPACE,
+        seeds = [b"license_state"],
+        bump
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::SPACE,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MintLicense<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        init,
+        payer = user,
+        mint::decimals = 0,
+        mint::authority = license_state,
+        mint::freeze_authority = license_state,
+        seeds = [b"mint", (license_state.total_minted + 1).to_le_bytes().as_ref()],
+        bump
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: This account will be created by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: This account will be created by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + LicenseMetadata::SPACE,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump
+    )]
+    pub license_metadata: Account<'info, LicenseMetadata>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + LicenseTermsAccount::SPACE,
+        seeds = [b"terms", mint.key().as_ref()],
+        bump
+    )]
+    pub license_terms: Box<Account<'info, LicenseTermsAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint,
+        associated_token::authority = user
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    // Existence of this PDA is what limits each address to one license. init_if_needed
+    // lets a repeat mint fail with AlreadyHasLicense instead of "already in use".
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + HolderReceipt::SPACE,
+        seeds = [b"holder", user.key().as_ref()],
+        bump,
+        constraint = holder_receipt.owner == Pubkey::default() @ LicenseError::AlreadyHasLicense
+    )]
+    pub holder_receipt: Account<'info, HolderReceipt>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    // Required only when license_state.payment_mint is set
+    #[account(mut)]
+    pub payer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub vault: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+
+    // Required once create_collection has run
+    /// CHECK: Checked against license_state.collection_mint
+    pub collection_mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Validated by Metaplex
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct AirdropLicense<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Box<Account<'info, LicenseState>>,
+
+    #[account(
+        init,
+        payer = minter,
+        mint::decimals = 0,
+        mint::authority = license_state,
+        mint::freeze_authority = license_state,
+        seeds = [b"mint", (license_state.total_minted + 1).to_le_bytes().as_ref()],
+        bump
+    )]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: This account will be created by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: This account will be created by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = minter,
+        space = 8 + LicenseMetadata::SPACE,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump
+    )]
+    pub license_metadata: Box<Account<'info, LicenseMetadata>>,
+
+    #[account(
+        init,
+        payer = minter,
+        space = 8 + LicenseTermsAccount::SPACE,
+        seeds = [b"terms", mint.key().as_ref()],
+        bump
+    )]
+    pub license_terms: Box<Account<'info, LicenseTermsAccount>>,
+
+    // Escrow that holds the license until the recipient runs claim_license
+    #[account(
+        init_if_needed,
+        payer = minter,
+        associated_token::mint = mint,
+        associated_token::authority = license_state
+    )]
+    pub token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = minter,
+        space = 8 + HolderReceipt::SPACE,
+        seeds = [b"holder", recipient.key().as_ref()],
+        bump,
+        constraint = holder_receipt.owner == Pubkey::default() @ LicenseError::AlreadyHasLicense
+    )]
+    pub holder_receipt: Box<Account<'info, HolderReceipt>>,
+
+    /// CHECK: Any wallet can receive a license
+    pub recipient: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"role".as_ref(), &[Role::Minter as u8], minter.key().as_ref()],
+        bump
+    )]
+    pub minter_role: Account<'info, RoleAssignment>,
+
+    #[account(mut)]
+    pub minter: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+
+    // Required once create_collection has run
+    /// CHECK: Checked against license_state.collection_mint
+    pub collection_mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Validated by Metaplex
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct BatchAirdropLicenses<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Box<Account<'info, LicenseState>>,
+
+    #[account(
+        seeds = [b"role".as_ref(), &[Role::Minter as u8], minter.key().as_ref()],
+        bump
+    )]
+    pub minter_role: Account<'info, RoleAssignment>,
+
+    #[account(mut)]
+    pub minter: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+
+    // Required once create_collection has run
+    /// CHECK: Checked against license_state.collection_mint
+    pub collection_mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Validated by Metaplex
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimLicense<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Box<Account<'info, LicenseState>>,
+
+    // Revoked licenses stay in escrow
+    #[account(
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint,
+        constraint = license_metadata.owner == recipient.key() @ LicenseError::Unauthorized,
+        constraint = license_metadata.revoked_at.is_none() @ LicenseError::LicenseRevoked
+    )]
+    pub license_metadata: Box<Account<'info, LicenseMetadata>>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = license_state
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = recipient,
+        associated_token::mint = mint,
+        associated_token::authority = recipient
+    )]
+    pub token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub recipient: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAirdrop<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Box<Account<'info, LicenseState>>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint
+    )]
+    pub license_metadata: Box<Account<'info, LicenseMetadata>>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"terms", mint.key().as_ref()],
+        bump
+    )]
+    pub license_terms: Option<Box<Account<'info, LicenseTermsAccount>>>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"holder", license_metadata.owner.as_ref()],
+        bump
+    )]
+    pub holder_receipt: Box<Account<'info, HolderReceipt>>,
+
+    #[account(mut)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    // Only unclaimed licenses are still held here
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = license_state
+    )]
+    pub escrow_token_account: Box<Account<'info, TokenAccount>>,
+
+    // Required for licenses verified into the collection, so its size stays accurate
+    /// CHECK: Validated by Metaplex
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct CreateCollection<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized,
+        constraint = license_state.collection_mint.is_none() @ LicenseError::CollectionAlreadyCreated
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = 0,
+        mint::authority = license_state,
+        mint::freeze_authority = license_state,
+        seeds = [b"collection"],
+        bump
+    )]
+    pub collection_mint: Account<'info, Mint>,
+
+    /// CHECK: This account will be created by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: This account will be created by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), collection_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = collection_mint,
+        associated_token::authority = license_state
+    )]
+    pub collection_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct GetNftMetadata<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    /// CHECK: Validated by mint address
+    pub metadata: UncheckedAccount<'info>,
+
+    pub license_metadata: Account<'info, LicenseMetadata>,
+
+    // Absent for licenses minted before terms were recorded on-chain
+    #[account(
+        seeds = [b"terms", license_metadata.mint.as_ref()],
+        bump
+    )]
+    pub license_terms: Option<Account<'info, LicenseTermsAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDefaults<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        seeds = [b"role".as_ref(), &[Role::MetadataEditor as u8], metadata_editor.key().as_ref()],
+        bump
+    )]
+    pub metadata_editor_role: Account<'info, RoleAssignment>,
+
+    pub metadata_editor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLicenseMetadata<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        seeds = [b"role".as_ref(), &[Role::MetadataEditor as u8], metadata_editor.key().as_ref()],
+        bump
+    )]
+    pub metadata_editor_role: Account<'info, RoleAssignment>,
+
+    // Licenses minted before revocation, validity and the longer provenance limit
+    // were added are smaller than LicenseMetadata::SPACE; grow them before writing
+    #[account(
+        mut,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint,
+        realloc = 8 + LicenseMetadata::SPACE,
+        realloc::payer = metadata_editor,
+        realloc::zero = false
+    )]
+    pub license_metadata: Account<'info, LicenseMetadata>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: Validated by seeds, written by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub metadata_editor: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLicenseState<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        constraint = license_state.pending_authority == Some(new_authority.key()) @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetLicenseFrozen<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    // Only Brolli mints have license metadata
+    #[account(
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint
+    )]
+    pub license_metadata: Account<'info, LicenseMetadata>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct TransferLicense<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Box<Account<'info, LicenseState>>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint,
+        constraint = license_metadata.owner == holder.key() @ LicenseError::Unauthorized,
+        constraint = license_metadata.revoked_at.is_none() @ LicenseError::LicenseRevoked
+    )]
+    pub license_metadata: Box<Account<'info, LicenseMetadata>>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = holder
+    )]
+    pub from_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = holder,
+        associated_token::mint = mint,
+        associated_token::authority = new_owner
+    )]
+    pub to_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"holder", holder.key().as_ref()],
+        bump
+    )]
+    pub from_receipt: Box<Account<'info, HolderReceipt>>,
+
+    // One license per address - fails if new_owner already holds a receipt
+    #[account(
+        init_if_needed,
+        payer = holder,
+        space = 8 + HolderReceipt::SPACE,
+        seeds = [b"holder", new_owner.key().as_ref()],
+        bump,
+        constraint = to_receipt.owner == Pubkey::default() @ LicenseError::AlreadyHasLicense
+    )]
+    pub to_receipt: Box<Account<'info, HolderReceipt>>,
+
+    // Any wallet without a license can receive one. Signs so license_state can be
+    // approved as delegate on the new token account before it is frozen.
+    pub new_owner: Signer<'info>,
+
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeLicense<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint,
+        constraint = license_metadata.revoked_at.is_none() @ LicenseError::LicenseRevoked
+    )]
+    pub license_metadata: Account<'info, LicenseMetadata>,
+
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub token_account: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct BurnLicense<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump
+    )]
+    pub license_state: Box<Account<'info, LicenseState>>,
+
+    // Revoked licenses keep their record
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"metadata", mint.key().as_ref()],
+        bump,
+        has_one = mint @ LicenseError::InvalidMint,
+        constraint = license_metadata.owner == holder.key() @ LicenseError::Unauthorized,
+        constraint = license_metadata.revoked_at.is_none() @ LicenseError::LicenseRevoked
+    )]
+    pub license_metadata: Box<Account<'info, LicenseMetadata>>,
+
+    // Absent for licenses minted before terms were recorded on-chain
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"terms", mint.key().as_ref()],
+        bump
+    )]
+    pub license_terms: Option<Box<Account<'info, LicenseTermsAccount>>>,
+
+    #[account(mut)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Validated by Metaplex
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref()],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by Metaplex; empty for licenses minted before master editions
+    #[account(
+        mut,
+        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = mpl_token_metadata::ID
+    )]
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = holder
+    )]
+    pub token_account: Box<Account<'info, TokenAccount>>,
+
+    // Required for licenses verified into the collection, so its size stays accurate
+    /// CHECK: Validated by Metaplex
+    #[account(mut)]
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"holder", holder.key().as_ref()],
+        bump
+    )]
+    pub holder_receipt: Box<Account<'info, HolderReceipt>>,
+
+    // Pays out any auction refund still owed on the receipt
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub holder: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+#[derive(Accounts)]
+pub struct RenewLicense<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        constraint = !license_state.paused @ LicenseError::Paused
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", license_metadata.mint.as_ref()],
+        bump,
+        constraint = license_metadata.revoked_at.is_none() @ LicenseError::LicenseRevoked
+    )]
+    pub license_metadata: Account<'info, LicenseMetadata>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        seeds = [b"role".as_ref(), &[Role::Pauser as u8], pauser.key().as_ref()],
+        bump
+    )]
+    pub pauser_role: Account<'info, RoleAssignment>,
+
+    pub pauser: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(role: Role, member: Pubkey)]
+pub struct GrantRole<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + RoleAssignment::SPACE,
+        seeds = [b"role".as_ref(), &[role as u8], member.as_ref()],
+        bump
+    )]
+    pub role_assignment: Account<'info, RoleAssignment>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(role: Role, member: Pubkey)]
+pub struct RevokeRole<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"role".as_ref(), &[role as u8], member.as_ref()],
+        bump
+    )]
+    pub role_assignment: Account<'info, RoleAssignment>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaymentMint<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub payment_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = payment_mint,
+        associated_token::authority = treasury
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTokens<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(
+        mut,
+        token::authority = treasury
+    )]
+    pub vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub recipient_token_account: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAuctionRefund<'info> {
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        mut,
+        seeds = [b"holder", user.key().as_ref()],
+        bump
+    )]
+    pub holder_receipt: Account<'info, HolderReceipt>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateLicenseState<'info> {
+    /// CHECK: Legacy layout is deserialized manually in the handler
+    #[account(
+        mut,
+        seeds = [b"license_state"],
+        bump,
+        owner = crate::ID
+    )]
+    pub license_state: UncheckedAccount<'info>,
+
+    /// CHECK: Created by the handler
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        seeds = [b"license_state"],
+        bump,
+        has_one = authority @ LicenseError::Unauthorized
+    )]
+    pub license_state: Account<'info, LicenseState>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    /// CHECK: Any account can receive lamports
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    pub authority: Signer<'info>,
+}
+
+// Removed GetSupplyInfo and HasLicense - read license_state account directly off-chain
+
+#[account]
+pub struct LicenseState {
+    pub authority: Pubkey,
+    pub current_supply: u64,
+    pub max_supply: u64,
+    pub default_image_uri: String,
+    pub default_provenance_cid: String,
+    pub mint_price: u64, // In lamports, collected into the treasury
+    pub payment_mint: Option<Pubkey>, // SPL token also charged on mint, if set
+    pub token_price: u64, // In payment_mint base units, collected into the treasury vault
+    pub auction: Option<AuctionConfig>, // Replaces mint_price while set
+    pub phase: MintPhase,
+    pub merkle_root: [u8; 32], // Allowlist of keccak(holder key) leaves
+    pub allowlist_discount_bps: u16,
+    pub pending_authority: Option<Pubkey>, // Proposed by authority, set once it accepts
+    pub paused: bool, // Blocks minting and metadata updates
+    pub total_minted: u64, // Token ids keep counting up even when burns free supply
+    pub burn_frees_supply: bool, // Whether burn_license decrements current_supply
+    pub license_duration: Option<i64>, // Seconds of validity per mint or renewal, None for perpetual
+    pub renewal_price: u64, // In lamports, collected into the treasury
+    pub collection_mint: Option<Pubkey>, // Set by create_collection
+    pub name_prefix: String, // Metaplex names are "<name_prefix> #<token_id> - <name>", ASCII hyphen
+    pub symbol: String,
+}
+
+impl LicenseState {
+    pub const SPACE: usize = 32 + 8 + 8 + (4 + MAX_URI_LENGTH) * 2 + 8 + (1 + 32) + 8 + (1 + AuctionConfig::SPACE) + 1 + 32 + 2 + (1 + 32) + 1 + 8 + 1 + (1 + 8) + 8 + (1 + 32) + (4 + mpl_token_metadata::MAX_NAME_LENGTH) + (4 + mpl_token_metadata::MAX_SYMBOL_LENGTH); // authority + current_supply + max_supply + default URIs + mint_price + payment_mint + token_price + auction + phase + merkle_root + allowlist_discount_bps + pending_authority + paused + total_minted + burn_frees_supply + license_duration + renewal_price + collection_mint + name_prefix + symbol
+
+    // Checks the supply limit and reserves the next token id
+    pub fn next_token_id(&mut self) -> Result<u64> {
+        require!(
+            self.current_supply < self.max_supply,
+            LicenseError::MaximumSupplyReached
+        );
+        self.current_supply += 1;
+        self.total_minted += 1;
+        Ok(self.total_minted)
+    }
+
+    // Falls back to the stored defaults for empty strings
+    pub fn resolve_uris(&self, image_uri: String, provenance_cid: String) -> (String, String) {
+        let image_uri = if image_uri.is_empty() {
+            self.default_image_uri.clone()
+        } else {
+            image_uri
+        };
+        let provenance_cid = if provenance_cid.is_empty() {
+            self.default_provenance_cid.clone()
+        } else {
+            provenance_cid
+        };
+        (image_uri, provenance_cid)
+    }
+
+    // Uses an ASCII hyphen rather than an en dash, which takes 3 of Metaplex's 32
+    // name bytes in UTF-8
+    pub fn display_name(&self, token_id: u64, name: &str) -> String {
+        format!("{} #{} - {}", self.name_prefix, token_id, name)
+    }
+
+    // Per-token Metaplex name, which must fit Metaplex's 32-byte limit
+    pub fn metaplex_name(&self, token_id: u64, name: &str) -> Result<String> {
+        let metaplex_name = self.display_name(token_id, name);
+        require!(
+            metaplex_name.len() <= mpl_token_metadata::MAX_NAME_LENGTH,
+            LicenseError::NameTooLong
+        );
+        Ok(metaplex_name)
+    }
+
+    // valid_from / valid_until for a license minted at `now`
+    pub fn validity_window(&self, now: i64) -> Result<(Option<i64>, Option<i64>)> {
+        let valid_until = match self.license_duration {
+            Some(duration) => Some(
+                now.checked_add(duration)
+                    .ok_or(LicenseError::LicenseValidityOverflow)?,
+            ),
+            None => None,
+        };
+        Ok((Some(now), valid_until))
+    }
+}
+
+// Roles held alongside the admin (LicenseState.authority), one PDA per role and member
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Minter,
+    MetadataEditor,
+    Pauser,
+}
+
+#[account]
+pub struct RoleAssignment {
+    pub role: Role,
+    pub member: Pubkey,
+}
+
+impl RoleAssignment {
+    pub const SPACE: usize = 1 + 32; // role + member
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum MintPhase {
+    Closed,
+    Allowlist,
+    Public,
+}
+
+// Dutch auction schedule plus the sales bookkeeping needed for settlement
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct AuctionConfig {
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+    pub decay_interval: i64, // Seconds between price drops
+    pub decay_step: u64, // Lamports dropped per interval
+    pub settlement: bool, // Refund early buyers down to the clearing price
+    pub first_token_id: u64,
+    pub last_price: u64,
+    pub sales: u64,
+    pub proceeds: u64,
+    pub clearing_price: Option<u64>,
+    pub pending_refunds: u64,
+}
+
+impl AuctionConfig {
+    pub const SPACE: usize = 8 + 8 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + (1 + 8) + 8;
+
+    pub fn price_at(&self, now: i64) -> u64 {
+        if let Some(clearing_price) = self.clearing_price {
+            return clearing_price;
+        }
+        let steps = (now.saturating_sub(self.start_time).max(0) / self.decay_interval) as u64;
+        self.start_price
+            .saturating_sub(steps.saturating_mul(self.decay_step))
+            .max(self.floor_price)
+    }
+
+    pub fn record_sale(&mut self, price: u64) {
+        if self.clearing_price.is_none() {
+            self.last_price = price;
+            self.sales += 1;
+            self.proceeds += price;
+        }
+    }
+
+    // Whether the receipt's license was sold by this auction, as opposed to an earlier
+    // one or an allowlist mint made while it ran
+    pub fn sold(&self, receipt: &HolderReceipt) -> bool {
+        receipt.auction_sale && receipt.token_id >= self.first_token_id
+    }
+
+    // Takes a burned license's sale out of the refund accounting. After settlement the
+    // refund still owed is returned for the caller to pay out; before it the sale is
+    // dropped from proceeds, forfeiting the refund it would have earned.
+    pub fn remove_sale(&mut self, receipt: &HolderReceipt) -> u64 {
+        if !self.settlement || !self.sold(receipt) {
+            return 0;
+        }
+        match self.clearing_price {
+            Some(clearing_price) => {
+                let refund = receipt.price_paid.saturating_sub(clearing_price);
+                self.pending_refunds -= refund;
+                refund
+            }
+            None => {
+                self.sales -= 1;
+                self.proceeds -= receipt.price_paid;
+                0
+            }
+        }
+    }
+
+    // Lamports the treasury must keep to honour refunds, before and after settlement
+    pub fn reserved_refunds(&self) -> u64 {
+        if !self.settlement {
+            return 0;
+        }
+        match self.clearing_price {
+            Some(_) => self.pending_refunds,
+            None => self.proceeds - self.floor_price * self.sales,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AirdropEntry {
+    pub recipient: Pubkey,
+    pub name: String,
+    pub image_uri: String,
+    pub provenance_cid: String,
+    pub terms: LicenseTerms,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AuctionParams {
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+    pub decay_interval: i64,
+    pub decay_step: u64,
+    pub settlement: bool,
+}
+
+// Holds mint proceeds until the authority withdraws them
+#[account]
+pub struct Treasury {}
+
+impl Treasury {
+    pub const SPACE: usize = 0;
+}
+
+// LicenseState layout of the original program version, read by migrate_license_state
+#[derive(AnchorDeserialize)]
+pub struct LegacyLicenseState {
+    pub authority: Pubkey,
+    pub current_supply: u64,
+    pub max_supply: u64,
+    pub has_license: Vec<Pubkey>,
+}
+
+impl LegacyLicenseState {
+    pub const SPACE: usize = 32 + 8 + 8 + (4 + 32 * MAX_LICENSES);
+}
+
+// One per license holder, seeded by the holder's key
+#[account]
+pub struct HolderReceipt {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub token_id: u64,
+    pub price_paid: u64, // Lamports paid at mint, lowered when an auction refund is claimed
+    pub auction_sale: bool, // Paid through a Dutch auction rather than a flat or allowlist price
+}
+
+impl HolderReceipt {
+    pub const SPACE: usize = 32 + 32 + 8 + 8 + 1; // owner + mint + token_id + price_paid + auction_sale
+}
+
+#[account]
+pub struct LicenseMetadata {
+    pub name: String,
+    pub image_uri: String,
+    pub provenance_cid: String,
+    pub owner: Pubkey, // Holder, or the recipient of an airdrop still in escrow
+    pub mint: Pubkey,
+    pub token_id: u64,
+    pub revoked_at: Option<i64>, // Set by revoke_license
+    pub revocation_reason: u16,
+    pub valid_from: Option<i64>,
+    pub valid_until: Option<i64>, // None for perpetual licenses
+}
+
+impl LicenseMetadata {
+    pub const SPACE: usize = (4 + MAX_NAME_LENGTH) + (4 + MAX_URI_LENGTH) * 2 + 32 + 32 + 8 + (1 + 8) + 2 + (1 + 8) * 2; // String lengths + Pubkeys + u64 + revocation status + validity window
+
+    pub fn is_valid_at(&self, now: i64) -> bool {
+        self.revoked_at.is_none()
+            && self.valid_from.unwrap_or(i64::MIN) <= now
+            && now < self.valid_until.unwrap_or(i64::MAX)
+    }
+}
+
+// Patent license terms, mirroring LicenseTerms in PatentLicenseNFT.sol
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LicenseTerms {
+    pub licensor_name: String,
+    pub patent_number: String,
+    pub patent_title: String,
+    pub governing_law: String,
+    pub provenance_hash: String,
+}
+
+impl LicenseTerms {
+    pub const SPACE: usize = (4 + MAX_LICENSOR_NAME_LENGTH) + (4 + MAX_PATENT_NUMBER_LENGTH) + (4 + MAX_PATENT_TITLE_LENGTH) + (4 + MAX_GOVERNING_LAW_LENGTH) + (4 + MAX_PROVENANCE_HASH_LENGTH);
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.licensor_name.len() <= MAX_LICENSOR_NAME_LENGTH
+                && self.patent_number.len() <= MAX_PATENT_NUMBER_LENGTH
+                && self.patent_title.len() <= MAX_PATENT_TITLE_LENGTH
+                && self.governing_law.len() <= MAX_GOVERNING_LAW_LENGTH
+                && self.provenance_hash.len() <= MAX_PROVENANCE_HASH_LENGTH,
+            LicenseError::TermsTooLong
+        );
+        // Drawn into the license SVG, so held to the same rules as names
+        require!(is_valid_name(&self.patent_number), LicenseError::InvalidName);
+        Ok(())
+    }
+}
+
+// One per license, seeded by its mint
+#[account]
+pub struct LicenseTermsAccount {
+    pub mint: Pubkey,
+    pub terms: LicenseTerms,
+}
+
+impl LicenseTermsAccount {
+    pub const SPACE: usize = 32 + LicenseTerms::SPACE; // mint + terms
+}
+
+// Removed SupplyInfo struct - read license_state fields directly
+
+fn apply_discount(price: u64, discount_bps: u16) -> u64 {
+    (price as u128 * (10_000 - discount_bps as u128) / 10_000) as u64
+}
+
+// Sorted-pair keccak Merkle proof, matching OpenZeppelin's MerkleProof
+fn verify_merkle_proof(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |hash, sibling| {
+        if hash <= *sibling {
+            keccak::hashv(&[&hash, sibling]).0
+        } else {
+            keccak::hashv(&[sibling, &hash]).0
+        }
+    });
+    computed == root
+}
+
+// Creates a PDA account with `space` bytes owned by `owner`, funded by `payer`.
+// Like Anchor's init, it copes with an address someone already sent lamports to,
+// so a stray transfer to a predictable PDA can't block creation.
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    new_account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let rent_exempt_lamports = Rent::get()?.minimum_balance(space);
+    let current_lamports = new_account.lamports();
+    if current_lamports == 0 {
+        return anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                anchor_lang::system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: new_account.clone(),
+                },
+                &[signer_seeds],
+            ),
+            rent_exempt_lamports,
+            space as u64,
+            owner,
+        );
+    }
+
+    let top_up = rent_exempt_lamports.saturating_sub(current_lamports);
+    if top_up > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: new_account.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Allocate {
+                account_to_allocate: new_account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        space as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Assign {
+                account_to_assign: new_account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        owner,
+    )
+}
+
+#[event]
+pub struct LicenseMinted {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub name: String,
+    pub provenance_cid: String,
+    pub token_id: u64,
+}
+
+#[event]
+pub struct DefaultUriUpdated {
+    pub uri_type: String,
+    pub new_uri: String,
+}
+
+#[event]
+pub struct MaxSupplyUpdated {
+    pub old_max_supply: u64,
+    pub new_max_supply: u64,
+}
+
+#[event]
+pub struct MintPriceUpdated {
+    pub old_mint_price: u64,
+    pub new_mint_price: u64,
+}
+
+#[event]
+pub struct FundsWithdrawn {
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
 
-        Ok(NftMetadataResponse {
-            name: format!("Brolli {}", license_metadata_account.name),
-            description: "For BUIDLers".to_string(),
-            image: license_metadata_account.image_uri.clone(),
-            provenance_cid: license_metadata_account.provenance_cid.clone(),
-            token_id: license_metadata_account.token_id,
-            owner: license_metadata_account.owner,
-            mint: license_metadata_account.mint,
-        })
-    }
+#[event]
+pub struct PaymentMintUpdated {
+    pub payment_mint: Option<Pubkey>,
+    pub token_price: u64,
+}
 
-    // Get provenance trait information specifically
-    pub fn get_provenance_trait(ctx: Context<GetNftMetadata>) -> Result<ProvenanceTrait> {
-        let license_metadata_account = &ctx.accounts.license_metadata;
+#[event]
+pub struct TokensWithdrawn {
+    pub mint: Pubkey,
+    pub recipient_token_account: Pubkey,
+    pub amount: u64,
+}
 
-        Ok(ProvenanceTrait {
-            trait_type: "Provenance CID".to_string(),
-            value: license_metadata_account.provenance_cid.clone(),
-        })
-    }
+#[event]
+pub struct AuctionConfigured {
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub start_time: i64,
+    pub decay_interval: i64,
+    pub decay_step: u64,
+    pub settlement: bool,
+}
 
-    // Note: Supply info and license check are available by reading license_state account off-chain
-    // No need for special instructions - just fetch the account data directly
+#[event]
+pub struct AuctionSettled {
+    pub clearing_price: u64,
+    pub sales: u64,
+    pub pending_refunds: u64,
 }
 
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + LicenseState::SPACE,
-        seeds = [b"license_state"],
-        bump
-    )]
-    pub license_state: Account<'info, LicenseState>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct AuctionRefundClaimed {
+    pub user: Pubkey,
+    pub amount: u64,
 }
 
-#[derive(Accounts)]
-pub struct MintLicense<'info> {
-    #[account(
-        mut,
-        seeds = [b"license_state"],
-        bump
-    )]
-    pub license_state: Account<'info, LicenseState>,
+#[event]
+pub struct AllowlistUpdated {
+    pub merkle_root: [u8; 32],
+    pub discount_bps: u16,
+}
 
-    #[account(
-        init,
-        payer = user,
-        mint::decimals = 0,
-        mint::authority = license_state,
-        mint::freeze_authority = license_state,
-        seeds = [b"mint", (license_state.current_supply + 1).to_le_bytes().as_ref()],
-        bump
-    )]
-    pub mint: Account<'info, Mint>,
+#[event]
+pub struct MintPhaseUpdated {
+    pub phase: MintPhase,
+}
 
-    /// CHECK: This account will be created by Metaplex
-    #[account(
-        mut,
-        seeds = [b"metadata", mpl_token_metadata::ID.as_ref(), mint.key().as_ref()],
-        bump,
-        seeds::program = mpl_token_metadata::ID
-    )]
-    pub metadata: UncheckedAccount<'info>,
+#[event]
+pub struct AuthorityTransferProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
 
-    #[account(
-        init,
-        payer = user,
-        space = 8 + LicenseMetadata::SPACE,
-        seeds = [b"metadata", mint.key().as_ref()],
-        bump
-    )]
-    pub license_metadata: Account<'info, LicenseMetadata>,
+#[event]
+pub struct AuthorityTransferCancelled {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
 
-    #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = mint,
-        associated_token::authority = user
-    )]
-    pub token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+#[event]
+pub struct RoleGranted {
+    pub role: Role,
+    pub member: Pubkey,
+}
 
-    pub rent: Sysvar<'info, Rent>,
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    /// CHECK: Validated by constraint
-    pub token_metadata_program: AccountInfo<'info>,
+#[event]
+pub struct RoleRevoked {
+    pub role: Role,
+    pub member: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct GetNftMetadata<'info> {
-    /// CHECK: Validated by mint address
-    pub metadata: UncheckedAccount<'info>,
+#[event]
+pub struct ProgramPaused {
+    pub pauser: Pubkey,
+}
 
-    pub license_metadata: Account<'info, LicenseMetadata>,
+#[event]
+pub struct ProgramUnpaused {
+    pub pauser: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct UpdateDefaults<'info> {
-    #[account(
-        mut,
-        seeds = [b"license_state"],
-        bump
-    )]
-    pub license_state: Account<'info, LicenseState>,
+#[event]
+pub struct LicenseFrozen {
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+}
 
-    #[account(mut)]
-    pub authority: Signer<'info>,
+#[event]
+pub struct LicenseThawed {
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
 }
 
-// Removed GetSupplyInfo and HasLicense - read license_state account directly off-chain
+#[event]
+pub struct LicenseClaimed {
+    pub mint: Pubkey,
+    pub token_id: u64,
+    pub owner: Pubkey,
+}
 
-#[account]
-pub struct LicenseState {
-    pub authority: Pubkey,
-    pub current_supply: u64,
-    pub max_supply: u64,
-    pub has_license: Vec<Pubkey>, // Track addresses that have licenses
+#[event]
+pub struct AirdropCancelled {
+    pub mint: Pubkey,
+    pub token_id: u64,
+    pub recipient: Pubkey,
 }
 
-impl LicenseState {
-    pub const SPACE: usize = 32 + 8 + 8 + (4 + 32 * MAX_LICENSES); // authority + current_supply + max_supply + Vec<Pubkey>
+#[event]
+pub struct LicenseTransferred {
+    pub mint: Pubkey,
+    pub token_id: u64,
+    pub from: Pubkey,
+    pub to: Pubkey,
 }
 
-#[account]
-pub struct LicenseMetadata {
-    pub name: String,
-    pub image_uri: String,
-    pub provenance_cid: String,
+#[event]
+pub struct LicenseRevoked {
+    pub mint: Pubkey,
+    pub token_id: u64,
     pub owner: Pubkey,
+    pub reason_code: u16,
+    pub revoked_at: i64,
+}
+
+#[event]
+pub struct LicenseBurned {
     pub mint: Pubkey,
     pub token_id: u64,
+    pub owner: Pubkey,
+    pub supply_freed: bool,
+    pub auction_refund: u64,
 }
 
-impl LicenseMetadata {
-    pub const SPACE: usize = 4 + 100 + 4 + 200 + 4 + 100 + 32 + 32 + 8; // String lengths + Pubkeys + u64
+#[event]
+pub struct BurnPolicyUpdated {
+    pub burn_frees_supply: bool,
 }
 
-// Removed SupplyInfo struct - read license_state fields directly
+#[event]
+pub struct LicenseTermsUpdated {
+    pub license_duration: Option<i64>,
+    pub renewal_price: u64,
+}
 
 #[event]
-pub struct LicenseMinted {
-    pub user: Pubkey,
+pub struct LicenseRenewed {
+    pub mint: Pubkey,
+    pub token_id: u64,
+    pub valid_until: i64,
+    pub price: u64,
+}
+
+#[event]
+pub struct LicenseMetadataUpdated {
     pub mint: Pubkey,
+    pub token_id: u64,
     pub name: String,
+    pub image_uri: String,
     pub provenance_cid: String,
-    pub token_id: u64,
 }
 
 #[event]
-pub struct DefaultUriUpdated {
-    pub uri_type: String,
-    pub new_uri: String,
+pub struct NameAndSymbolUpdated {
+    pub name_prefix: String,
+    pub symbol: String,
+}
+
+#[event]
+pub struct CollectionCreated {
+    pub collection_mint: Pubkey,
 }
 
 #[error_code]
@@ -345,6 +3837,76 @@ pub enum LicenseError {
     Unauthorized,
     #[msg("Invalid mint address")]
     InvalidMint,
+    #[msg("URI exceeds maximum length")]
+    UriTooLong,
+    #[msg("License state already migrated")]
+    AlreadyMigrated,
+    #[msg("Max supply cannot be below current supply")]
+    MaxSupplyBelowCurrentSupply,
+    #[msg("Insufficient treasury funds")]
+    InsufficientTreasuryFunds,
+    #[msg("Payment token accounts are required")]
+    MissingPaymentAccounts,
+    #[msg("Invalid payment vault")]
+    InvalidVault,
+    #[msg("Invalid auction configuration")]
+    InvalidAuctionConfig,
+    #[msg("Auction has not started")]
+    AuctionNotStarted,
+    #[msg("Auction has not ended")]
+    AuctionNotEnded,
+    #[msg("Auction already settled")]
+    AuctionAlreadySettled,
+    #[msg("Auction has unsettled sales or unclaimed refunds")]
+    AuctionInProgress,
+    #[msg("No auction configured")]
+    NoAuction,
+    #[msg("No refund due")]
+    NoRefundDue,
+    #[msg("Minting is not open in this phase")]
+    WrongPhase,
+    #[msg("Invalid allowlist proof")]
+    InvalidMerkleProof,
+    #[msg("Discount cannot exceed 10000 basis points")]
+    InvalidDiscount,
+    #[msg("No authority transfer pending")]
+    NoPendingAuthority,
+    #[msg("Program is paused")]
+    Paused,
+    #[msg("Program is not paused")]
+    NotPaused,
+    #[msg("License has been revoked")]
+    LicenseRevoked,
+    #[msg("License duration must be positive")]
+    InvalidLicenseDuration,
+    #[msg("License is not time-bound")]
+    LicenseNotRenewable,
+    #[msg("License terms exceed maximum length")]
+    TermsTooLong,
+    #[msg("Token URI exceeds the return data limit")]
+    TokenUriTooLarge,
+    #[msg("Collection has already been created")]
+    CollectionAlreadyCreated,
+    #[msg("Collection accounts are required once the collection exists")]
+    CollectionAccountsMissing,
+    #[msg("Collection mint does not match")]
+    InvalidCollection,
+    #[msg("License token account has not approved license_state as delegate")]
+    LicenseNotDelegated,
+    #[msg("Name exceeds maximum length")]
+    NameTooLong,
+    #[msg("Symbol exceeds maximum length")]
+    SymbolTooLong,
+    #[msg("Provenance must be a CIDv0, CIDv1, ipfs:// URI or IPFS gateway URL")]
+    InvalidCid,
+    #[msg("License validity end overflows")]
+    LicenseValidityOverflow,
+    #[msg("Default provenance CID cannot be empty")]
+    MissingProvenanceCid,
+    #[msg("Name contains control characters")]
+    InvalidName,
+    #[msg("URI must be printable ASCII without quotes or backslashes")]
+    InvalidUri,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -356,10 +3918,367 @@ pub struct NftMetadataResponse {
     pub token_id: u64,
     pub owner: Pubkey,
     pub mint: Pubkey,
+    pub revoked: bool,
+    pub revoked_at: Option<i64>,
+    pub revocation_reason: u16,
+    pub valid_from: Option<i64>,
+    pub valid_until: Option<i64>,
+    pub terms: Option<LicenseTerms>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct ProvenanceTrait {
     pub trait_type: String,
     pub value: String,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn license_state(name_prefix: &str) -> LicenseState {
+        LicenseState {
+            authority: Pubkey::default(),
+            current_supply: 0,
+            max_supply: 100,
+            default_image_uri: String::new(),
+            default_provenance_cid: DEFAULT_PROVENANCE_CID.to_string(),
+            mint_price: 0,
+            payment_mint: None,
+            token_price: 0,
+            auction: None,
+            phase: MintPhase::Public,
+            merkle_root: [0; 32],
+            allowlist_discount_bps: 0,
+            pending_authority: None,
+            paused: false,
+            total_minted: 0,
+            burn_frees_supply: false,
+            license_duration: None,
+            renewal_price: 0,
+            collection_mint: None,
+            name_prefix: name_prefix.to_string(),
+            symbol: DEFAULT_SYMBOL.to_string(),
+        }
+    }
+
+    // Longest prefix that still fits "<prefix> #1 - ", the first token with an empty name
+    const MAX_NAME_PREFIX_LENGTH: usize = mpl_token_metadata::MAX_NAME_LENGTH - " #1 - ".len();
+
+    #[test]
+    fn metaplex_name_limits_the_prefix_by_the_token_id() {
+        let state = license_state(&"Brolli License".repeat(2));
+        assert!(state.metaplex_name(1, "").is_err());
+
+        let state = license_state("Brolli License");
+        assert_eq!(state.metaplex_name(1, "Ada Lovelace").unwrap(), "Brolli License #1 - Ada Lovelace");
+        assert!(state.metaplex_name(1, "Ada Lovelacé").is_err());
+
+        let state = license_state(&"P".repeat(MAX_NAME_PREFIX_LENGTH));
+        assert!(state.metaplex_name(1, "").is_ok());
+        assert!(state.metaplex_name(10, "").is_err());
+
+        let state = license_state(DEFAULT_NAME_PREFIX);
+        assert_eq!(state.metaplex_name(7, "Ada").unwrap(), "Brolli #7 - Ada");
+    }
+
+    #[test]
+    fn resolve_uris_replaces_empty_values_with_the_defaults() {
+        let state = license_state(DEFAULT_NAME_PREFIX);
+        assert_eq!(
+            state.resolve_uris(String::new(), String::new()),
+            (String::new(), DEFAULT_PROVENANCE_CID.to_string())
+        );
+        assert_eq!(
+            state.resolve_uris("ipfs://image".to_string(), "ipfs://provenance".to_string()),
+            ("ipfs://image".to_string(), "ipfs://provenance".to_string())
+        );
+    }
+
+    const CIDV0: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    const CIDV1_DAG_PB: &str = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi";
+    const CIDV1_RAW: &str = "bafkreialme2ca3b36nzq5rqqdqaw3k2le4uvgrdxtdj33t2j4sn44amisi";
+
+    #[test]
+    fn is_valid_cid_accepts_each_supported_encoding() {
+        assert!(is_valid_cid(CIDV0));
+        assert!(is_valid_cid(CIDV1_DAG_PB));
+        assert!(is_valid_cid(CIDV1_RAW));
+        assert!(is_valid_cid(
+            "f01701220c3c4733ec8affd06cf9e9ff50ffc6bcd2ec85a6170004bb709669c31de94391a"
+        ));
+        assert!(is_valid_cid("zdj7WWeQ43G6JJvLWQWZpyHuAMq6uYWRjkBXFad11vE2LHhQ7"));
+    }
+
+    #[test]
+    fn is_valid_cid_rejects_malformed_cids() {
+        // CIDv0 one character short, and one too long
+        assert!(!is_valid_cid(&CIDV0[..45]));
+        assert!(!is_valid_cid(&format!("{}a", CIDV0)));
+        // Multibase prefixes are case-sensitive and base32 upper isn't supported
+        assert!(!is_valid_cid(&CIDV1_DAG_PB.to_uppercase()));
+        // Version byte must be 0x01
+        assert!(!is_valid_cid("f00701220c3c4733ec8affd06cf9e9ff50ffc6bcd2ec85a6170004bb709669c31de94391a"));
+        // Base58 has no 0, O, I or l
+        assert!(!is_valid_cid("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbd0"));
+        assert!(!is_valid_cid("bafy"));
+        assert!(!is_valid_cid(""));
+    }
+
+    #[test]
+    fn is_valid_provenance_accepts_cids_ipfs_uris_and_gateways() {
+        assert!(is_valid_provenance(CIDV0));
+        assert!(is_valid_provenance(&format!("ipfs://{}", CIDV1_DAG_PB)));
+        assert!(is_valid_provenance(&format!("ipfs://{}/license.pdf", CIDV1_DAG_PB)));
+        assert!(is_valid_provenance(DEFAULT_PROVENANCE_CID));
+        assert!(is_valid_provenance(&format!("http://localhost:8080/ipfs/{}", CIDV0)));
+        assert!(is_valid_provenance(&format!(
+            "https://ipfs.io/ipfs/{}/license.pdf?filename=license.pdf",
+            CIDV1_RAW
+        )));
+        assert!(is_valid_provenance(&format!("https://{}.ipfs.dweb.link", CIDV1_DAG_PB)));
+        assert!(is_valid_provenance(&format!("https://{}.ipfs.w3s.link/license.pdf", CIDV1_RAW)));
+    }
+
+    #[test]
+    fn is_valid_provenance_rejects_non_ipfs_locations() {
+        assert!(!is_valid_provenance(&format!("ipfs://{}", &CIDV0[..45])));
+        assert!(!is_valid_provenance(&format!("https://ipfs.io/ipfs/{}", CIDV1_DAG_PB.to_uppercase())));
+        // Subdomain form with nothing after ".ipfs."
+        assert!(!is_valid_provenance(&format!("https://{}.ipfs.", CIDV1_DAG_PB)));
+        assert!(!is_valid_provenance(&format!("https://{}.ipfs./license.pdf", CIDV1_DAG_PB)));
+        assert!(!is_valid_provenance("https://example.com/license.pdf"));
+        assert!(!is_valid_provenance(&format!("ftp://ipfs.io/ipfs/{}", CIDV0)));
+        assert!(!is_valid_provenance("ipfs://"));
+    }
+
+    // The longest name a license with this token id can have been minted with, i.e.
+    // "<prefix> #<token_id> - <name>" at Metaplex's limit with an empty prefix, plus the
+    // longest patent number and expiry. "'" is the widest character names allow.
+    fn widest_license(token_id: u64) -> (LicenseMetadata, LicenseTermsAccount) {
+        let name_length = mpl_token_metadata::MAX_NAME_LENGTH
+            - format!(" #{} - ", token_id).len();
+        let license_metadata = LicenseMetadata {
+            name: "'".repeat(name_length),
+            image_uri: String::new(),
+            provenance_cid: "u".repeat(MAX_URI_LENGTH),
+            owner: Pubkey::default(),
+            mint: Pubkey::default(),
+            token_id,
+            revoked_at: None,
+            revocation_reason: 0,
+            valid_from: None,
+            valid_until: Some(i64::MAX),
+        };
+        let license_terms = LicenseTermsAccount {
+            mint: Pubkey::default(),
+            terms: LicenseTerms {
+                licensor_name: String::new(),
+                patent_number: "'".repeat(MAX_PATENT_NUMBER_LENGTH),
+                patent_title: String::new(),
+                governing_law: String::new(),
+                provenance_hash: String::new(),
+            },
+        };
+        (license_metadata, license_terms)
+    }
+
+    #[test]
+    fn worst_case_token_uri_with_embedded_svg_fits_in_return_data() {
+        // The prefix may have been lengthened since the license was minted
+        let state = license_state(&"P".repeat(MAX_NAME_PREFIX_LENGTH));
+        for token_id in [1, u64::MAX] {
+            let (license_metadata, license_terms) = widest_license(token_id);
+            let token_uri = build_token_uri(
+                &state.display_name(token_id, &license_metadata.name),
+                &svg_data_uri(&render_license_svg(&license_metadata, Some(&license_terms))),
+                &license_metadata.provenance_cid,
+            );
+            assert!(4 + token_uri.len() <= MAX_RETURN_DATA, "{} bytes", token_uri.len());
+        }
+    }
+
+    #[test]
+    fn token_uri_embeds_the_svg_as_its_image() {
+        let (license_metadata, license_terms) = widest_license(7);
+        let svg = render_license_svg(&license_metadata, Some(&license_terms));
+        let token_uri = build_token_uri("Brolli #7 - Ada", &svg_data_uri(&svg), DEFAULT_PROVENANCE_CID);
+        let json = general_purpose::STANDARD
+            .decode(token_uri.strip_prefix("data:application/json;base64,").unwrap())
+            .unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"image\":\"data:image/svg+xml;utf8,<svg "));
+        assert!(json.contains("<text x='4' y='24' font-size='6'>%237</text>"));
+        let image = json.split("\"image\":\"").nth(1).unwrap().split('"').next().unwrap();
+        assert!(!image.contains('#'));
+        assert!(json.contains(DEFAULT_PROVENANCE_CID));
+    }
+
+    #[test]
+    fn is_valid_name_accepts_plain_text_only() {
+        assert!(is_valid_name("Ada Lovelace"));
+        assert!(is_valid_name("Zoë O'Brien-Smith, Ltd."));
+        assert!(is_valid_name(""));
+        assert!(!is_valid_name("Ada\u{0}"));
+        assert!(!is_valid_name("Ada\nLovelace"));
+        for c in ['"', '\\', '<', '&', '#', '%'] {
+            assert!(!is_valid_name(&format!("Ada {} Co", c)), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn is_valid_uri_accepts_printable_ascii_without_quotes() {
+        assert!(is_valid_uri(DEFAULT_PROVENANCE_CID));
+        assert!(is_valid_uri("https://example.com/a.png?size=2&fit=cover#top"));
+        assert!(is_valid_uri(""));
+        assert!(!is_valid_uri("https://example.com/\"a\""));
+        assert!(!is_valid_uri("https://example.com/a\\b"));
+        assert!(!is_valid_uri("https://example.com/a b"));
+        assert!(!is_valid_uri("https://example.com/caf\u{e9}"));
+    }
+
+    #[test]
+    fn format_date_converts_unix_timestamps() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(86_399), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_date(1_709_251_200), "2024-03-01");
+        assert_eq!(format_date(4_102_444_799), "2099-12-31");
+        assert_eq!(format_date(-1), "1969-12-31");
+        assert_eq!(format_date(-86_400 * 365), "1969-01-01");
+    }
+
+    fn auction(settlement: bool) -> AuctionConfig {
+        AuctionConfig {
+            start_price: 1_000,
+            floor_price: 400,
+            start_time: 100,
+            decay_interval: 10,
+            decay_step: 50,
+            settlement,
+            first_token_id: 1,
+            last_price: 0,
+            sales: 0,
+            proceeds: 0,
+            clearing_price: None,
+            pending_refunds: 0,
+        }
+    }
+
+    #[test]
+    fn price_at_decays_in_steps_down_to_the_floor() {
+        let auction = auction(false);
+        assert_eq!(auction.price_at(0), 1_000);
+        assert_eq!(auction.price_at(100), 1_000);
+        assert_eq!(auction.price_at(109), 1_000);
+        assert_eq!(auction.price_at(110), 950);
+        assert_eq!(auction.price_at(195), 550);
+        assert_eq!(auction.price_at(220), 400);
+        assert_eq!(auction.price_at(i64::MAX), 400);
+    }
+
+    #[test]
+    fn price_at_is_the_clearing_price_once_settled() {
+        let mut auction = auction(false);
+        auction.clearing_price = Some(700);
+        assert_eq!(auction.price_at(0), 700);
+        assert_eq!(auction.price_at(i64::MAX), 700);
+    }
+
+    #[test]
+    fn reserved_refunds_tracks_the_floor_until_settled() {
+        let mut auction = auction(true);
+        auction.record_sale(1_000);
+        auction.record_sale(800);
+        assert_eq!(auction.reserved_refunds(), 1_800 - 2 * 400);
+
+        auction.clearing_price = Some(800);
+        auction.pending_refunds = 200;
+        assert_eq!(auction.reserved_refunds(), 200);
+    }
+
+    #[test]
+    fn reserved_refunds_is_zero_without_settlement() {
+        let mut auction = auction(false);
+        auction.record_sale(1_000);
+        assert_eq!(auction.reserved_refunds(), 0);
+    }
+
+    #[test]
+    fn sold_excludes_allowlist_mints_and_earlier_auctions() {
+        let mut auction = auction(true);
+        auction.first_token_id = 5;
+        let receipt = |token_id, auction_sale| HolderReceipt {
+            owner: Pubkey::default(),
+            mint: Pubkey::default(),
+            token_id,
+            price_paid: 1_000,
+            auction_sale,
+        };
+
+        assert!(auction.sold(&receipt(5, true)));
+        assert!(!auction.sold(&receipt(5, false)));
+        assert!(!auction.sold(&receipt(4, true)));
+    }
+
+    #[test]
+    fn remove_sale_settles_or_forfeits_the_refund() {
+        let receipt = HolderReceipt {
+            owner: Pubkey::default(),
+            mint: Pubkey::default(),
+            token_id: 1,
+            price_paid: 1_000,
+            auction_sale: true,
+        };
+
+        let mut unsettled = auction(true);
+        unsettled.record_sale(1_000);
+        unsettled.record_sale(800);
+        assert_eq!(unsettled.remove_sale(&receipt), 0);
+        assert_eq!((unsettled.sales, unsettled.proceeds), (1, 800));
+        assert_eq!(unsettled.reserved_refunds(), 800 - 400);
+
+        let mut settled = auction(true);
+        settled.clearing_price = Some(800);
+        settled.pending_refunds = 200;
+        assert_eq!(settled.remove_sale(&receipt), 200);
+        assert_eq!(settled.reserved_refunds(), 0);
+    }
+
+    fn sorted_pair_hash(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+        if a <= b {
+            keccak::hashv(&[&a, &b]).0
+        } else {
+            keccak::hashv(&[&b, &a]).0
+        }
+    }
+
+    #[test]
+    fn verify_merkle_proof_accepts_every_allowlisted_leaf() {
+        let leaves: Vec<[u8; 32]> = (1..=4u8)
+            .map(|i| keccak::hashv(&[Pubkey::new_from_array([i; 32]).as_ref()]).0)
+            .collect();
+        let left = sorted_pair_hash(leaves[0], leaves[1]);
+        let right = sorted_pair_hash(leaves[2], leaves[3]);
+        let root = sorted_pair_hash(left, right);
+
+        assert!(verify_merkle_proof(&[leaves[1], right], root, leaves[0]));
+        assert!(verify_merkle_proof(&[leaves[0], right], root, leaves[1]));
+        assert!(verify_merkle_proof(&[leaves[3], left], root, leaves[2]));
+        assert!(verify_merkle_proof(&[leaves[2], left], root, leaves[3]));
+    }
+
+    #[test]
+    fn verify_merkle_proof_rejects_bad_proofs() {
+        let leaf = keccak::hashv(&[b"holder"]).0;
+        let sibling = keccak::hashv(&[b"sibling"]).0;
+        let root = sorted_pair_hash(leaf, sibling);
+        let outsider = keccak::hashv(&[b"outsider"]).0;
+
+        assert!(verify_merkle_proof(&[], leaf, leaf));
+        assert!(!verify_merkle_proof(&[sibling], root, outsider));
+        assert!(!verify_merkle_proof(&[outsider], root, leaf));
+        assert!(!verify_merkle_proof(&[], root, leaf));
+    }
+}